@@ -0,0 +1,23 @@
+use anyhow::Result;
+use dm_ticket::mock::{serve, MockScenario};
+use dotenv::dotenv;
+use std::env;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "INFO");
+    }
+
+    pretty_env_logger::init();
+
+    // 演练脚本路径, 不传则使用默认场景
+    let scenario = match env::args().nth(1) {
+        Some(path) => MockScenario::load(&path)?,
+        None => MockScenario::default(),
+    };
+
+    serve(scenario).await
+}
@@ -0,0 +1,415 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    models::{DmRes, DmToken},
+    transport::{MtopRequest, Transport},
+};
+
+// 脱敏后的占位值
+pub const REDACTED: &str = "<redacted>";
+
+// 需要脱敏的字段, 包括签名、cookie和令牌
+const SENSITIVE_KEYS: [&str; 9] = [
+    "sign",
+    "bx-umidtoken",
+    "bx-ua",
+    "cookie",
+    "_m_h5_tk",
+    "_m_h5_tk_enc",
+    "token",
+    "umidToken",
+    "ua",
+];
+
+// 递归替换敏感字段的值
+pub fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                if SENSITIVE_KEYS.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+                    *v = REDACTED.into();
+                } else {
+                    redact(v);
+                }
+            }
+        }
+        Value::Array(list) => list.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+// 实名观演人姓名只保留姓, 证件号只保留后4位, 回放时仍可按证件号后4位选择观演人
+fn mask(key: &str, value: &str) -> Option<String> {
+    let len = value.chars().count();
+    let keep = match key {
+        "viewerName" => 1,
+        "certNo" => 4,
+        _ => return None,
+    };
+    let stars = "*".repeat(len.saturating_sub(keep));
+    Some(match key {
+        "viewerName" => value.chars().take(keep).collect::<String>() + &stars,
+        _ => {
+            stars
+                + &value
+                    .chars()
+                    .skip(len.saturating_sub(keep))
+                    .collect::<String>()
+        }
+    })
+}
+
+// 脱敏JSON编码的字符串, 如提交订单的params及其中的data, 有改动时重新编码
+fn mask_json_string(value: &mut Value) {
+    let nested = value
+        .as_str()
+        .filter(|s| s.starts_with('{') || s.starts_with('['))
+        .and_then(|s| serde_json::from_str::<Value>(s).ok());
+    if let Some(mut nested) = nested {
+        let original = nested.clone();
+        mask_viewers(&mut nested);
+        if nested != original {
+            *value = nested.to_string().into();
+        }
+    }
+}
+
+// 递归脱敏实名观演人信息, 请求和响应中的viewerList都包含姓名和证件号
+pub fn mask_viewers(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                match v.as_str().and_then(|s| mask(key, s)) {
+                    Some(masked) => *v = masked.into(),
+                    None => mask_viewers(v),
+                }
+            }
+        }
+        Value::Array(list) => list.iter_mut().for_each(mask_viewers),
+        Value::String(_) => mask_json_string(value),
+        _ => {}
+    }
+}
+
+// 一次请求的抓包记录, 每行一条写入JSONL文件
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Capture {
+    pub api: String,
+    pub path: String,
+    pub timestamp: i64,
+    pub params: Value,
+    // 请求的data参数
+    pub data: Value,
+    // 未收到响应时为None
+    pub response: Option<Value>,
+    pub error: Option<String>,
+}
+
+impl Capture {
+    pub fn new(request: &MtopRequest, res: &Result<DmRes>) -> Self {
+        let mut params = request.params.clone();
+        redact(&mut params);
+
+        let mut data = request.form["data"]
+            .as_str()
+            .and_then(|d| serde_json::from_str::<Value>(d).ok())
+            .unwrap_or_default();
+        redact(&mut data);
+        mask_viewers(&mut data);
+
+        let mut response = res.as_ref().ok().and_then(|r| serde_json::to_value(r).ok());
+        if let Some(response) = response.as_mut() {
+            mask_viewers(response);
+        }
+
+        Self {
+            api: request.params["api"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            path: request.path.clone(),
+            timestamp: Local::now().timestamp_millis(),
+            params,
+            data,
+            response,
+            error: res.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+
+    // 解析记录的响应
+    pub fn response(&self) -> Result<DmRes> {
+        match (&self.response, &self.error) {
+            (Some(response), _) => Ok(serde_json::from_value(response.clone())?),
+            (None, Some(e)) => Err(anyhow!("{}", e)),
+            (None, None) => Err(anyhow!("{}没有记录响应", self.api)),
+        }
+    }
+}
+
+// 读取抓包文件
+pub fn load_captures(path: &str) -> Result<Vec<Capture>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|e| anyhow!("{}第{}行解析失败, {}", path, i + 1, e))
+        })
+        .collect()
+}
+
+// 以追加方式写入抓包文件
+pub struct CaptureWriter {
+    file: Mutex<File>,
+}
+
+impl CaptureWriter {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn write(&self, capture: &Capture) -> Result<()> {
+        let mut line = serde_json::to_string(capture)?;
+        line.push('\n');
+        self.file.lock().unwrap().write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// 按顺序回放抓包记录的Transport.
+///
+/// 每个api的记录按抓包顺序依次返回, 不同api之间互不影响,
+/// 所以预热、校时等请求的次数不同也不会打乱回放.
+pub struct ReplayTransport {
+    captures: Mutex<VecDeque<Capture>>,
+}
+
+impl ReplayTransport {
+    pub fn new(captures: Vec<Capture>) -> Self {
+        Self {
+            captures: Mutex::new(captures.into()),
+        }
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self::new(load_captures(path)?))
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: MtopRequest) -> BoxFuture<'_, Result<DmRes>> {
+        Box::pin(async move {
+            let api = request.params["api"].as_str().unwrap_or_default();
+            let capture = {
+                let mut captures = self.captures.lock().unwrap();
+                captures
+                    .iter()
+                    .position(|c| c.api == api)
+                    .and_then(|i| captures.remove(i))
+            };
+            match capture {
+                Some(capture) => capture.response(),
+                None => Err(anyhow!("回放记录中没有更多的{}请求", api)),
+            }
+        })
+    }
+
+    fn fetch_token<'a>(&'a self, _cookie: &'a str) -> BoxFuture<'a, Result<DmToken>> {
+        Box::pin(async move {
+            Ok(DmToken {
+                token_with_time: format!("replay_{}", Local::now().timestamp_millis()),
+                token: "replay".to_string(),
+                enc_token: "replay".to_string(),
+            })
+        })
+    }
+
+    fn server_date(&self) -> BoxFuture<'_, Result<i64>> {
+        Box::pin(async move { Ok(Local::now().timestamp_millis()) })
+    }
+
+    // 抓包记录中bx-umidtoken/bx-ua已脱敏, 回放时不请求token-server
+    fn bx_token(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(async move { Ok(REDACTED.to_string()) })
+    }
+
+    fn bx_ua(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(async move { Ok(REDACTED.to_string()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{client::DmClient, config::Account, dm::DmTicket};
+
+    // 对dm-mock-server试运行时抓包: MTOP_CAPTURE_FILE=dry_run.jsonl dm-ticket dry-run
+    const DRY_RUN_CAPTURE: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/dry_run.jsonl");
+
+    const ACCOUNT: &str = r#"
+cookie: "cna=abc; cookie2=xyz"
+remark: 回放
+viewers: ["1001"]
+ticket:
+  id: "710947802955"
+  num: 1
+  sessions: 2
+  grade: 680
+"#;
+
+    async fn replay(path: &str) -> DmTicket {
+        let account: Account = serde_yaml::from_str(ACCOUNT).unwrap();
+        let transport = Arc::new(ReplayTransport::open(path).unwrap());
+        let client = DmClient::with_transport(account.cookie.clone(), transport)
+            .await
+            .unwrap();
+        DmTicket::with_client(account, client)
+    }
+
+    #[tokio::test]
+    async fn replay_dry_run() {
+        let dm_ticket = replay(DRY_RUN_CAPTURE).await;
+        let ticket_id = dm_ticket.account.ticket.id.clone();
+
+        let ticket_info = dm_ticket.get_ticket_info(ticket_id.clone()).await.unwrap();
+        let item = &ticket_info.detail_view_component_map.item;
+        assert_eq!(item.static_data.item_base.item_name, "模拟演唱会");
+        let perform = dm_ticket
+            .account
+            .ticket
+            .sessions
+            .resolve(&item.item.perform_bases)
+            .unwrap();
+        assert_eq!(perform.perform_id, "200001");
+
+        let perform_info = dm_ticket
+            .get_perform_info(ticket_id, perform.perform_id.clone())
+            .await
+            .unwrap();
+        let sku = dm_ticket
+            .account
+            .ticket
+            .grade
+            .resolve(&perform_info.perform.sku_list)
+            .unwrap();
+        assert_eq!(sku.price_name, "看台680元");
+
+        let order_info = dm_ticket
+            .build_order(&sku.item_id, &sku.sku_id)
+            .await
+            .unwrap();
+        assert_eq!(dm_ticket.check_viewers(&order_info).unwrap(), None);
+
+        let order_data = dm_ticket.build_order_data(&order_info).unwrap();
+        let viewers = order_info
+            .linkage
+            .input
+            .iter()
+            .filter(|key| key.starts_with("dmViewer_"))
+            .filter_map(|key| order_data[key]["fields"]["viewerList"].as_array())
+            .flatten()
+            .filter(|v| v["isUsed"].as_bool().unwrap_or(false))
+            .map(|v| v["viewerName"].as_str().unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(viewers, ["李*"]);
+
+        // 每个请求只回放一次
+        let err = dm_ticket
+            .build_order(&sku.item_id, &sku.sku_id)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("回放记录中没有更多的"), "{}", err);
+    }
+
+    #[test]
+    fn captures_are_redacted() {
+        for capture in load_captures(DRY_RUN_CAPTURE).unwrap() {
+            for key in ["sign", "bx-umidtoken", "bx-ua"] {
+                if let Some(value) = capture.params.get(key) {
+                    assert_eq!(value, REDACTED, "{} {}", capture.api, key);
+                }
+            }
+            let mut masked = capture.clone();
+            mask_viewers(&mut masked.data);
+            if let Some(response) = masked.response.as_mut() {
+                mask_viewers(response);
+            }
+            assert_eq!(masked.data, capture.data, "{}", capture.api);
+            assert_eq!(masked.response, capture.response, "{}", capture.api);
+        }
+    }
+
+    #[test]
+    fn mask_viewer_info() {
+        let viewer_list = serde_json::json!({"viewerList": [
+            {"viewerId": "1000", "viewerName": "张三", "certNo": "110101199001011000"},
+            {"viewerId": "1001", "viewerName": "欧阳娜娜", "certNo": "110101********1001"},
+            {"viewerId": "1002", "viewerName": "", "certNo": "123"},
+        ]});
+        let request = MtopRequest {
+            path: "h5/mtop.trade.order.create.h5/4.0/".to_string(),
+            params: serde_json::json!({"api": "mtop.trade.order.create.h5"}),
+            form: serde_json::json!({"data": viewer_list.to_string()}),
+            cookie: String::new(),
+        };
+        let res = DmRes {
+            api: None,
+            data: serde_json::json!({"dmViewer_1": {"fields": viewer_list}}),
+            ret: vec![],
+            v: None,
+        };
+
+        let capture = Capture::new(&request, &Ok(res));
+        let response = capture.response().unwrap();
+        let expected = [
+            ("1000", "张*", "**************1000"),
+            ("1001", "欧***", "**************1001"),
+            ("1002", "", "123"),
+        ];
+        let check = |list: &Value| {
+            for (viewer, (id, name, cert_no)) in list.as_array().unwrap().iter().zip(expected) {
+                assert_eq!(viewer["viewerId"], id);
+                assert_eq!(viewer["viewerName"], name);
+                assert_eq!(viewer["certNo"], cert_no);
+            }
+        };
+        check(&capture.data["viewerList"]);
+        check(&response.data["dmViewer_1"]["fields"]["viewerList"]);
+
+        // 提交订单的data: params和params.data都是JSON编码的字符串
+        let order_data = serde_json::json!({"dmViewer_1": {"fields": viewer_list}});
+        let params = serde_json::json!({"data": order_data.to_string(), "linkage": "{}"});
+        let submit_data = serde_json::json!({"params": params.to_string(), "feature": "{}"});
+        let request = MtopRequest {
+            form: serde_json::json!({"data": submit_data.to_string()}),
+            ..request
+        };
+        let capture = Capture::new(&request, &Err(anyhow!("限流")));
+        let line = serde_json::to_string(&capture).unwrap();
+        assert!(!line.contains("110101199001011000"), "{}", line);
+        assert!(!line.contains("张三"), "{}", line);
+
+        let parse = |v: &Value| serde_json::from_str::<Value>(v.as_str().unwrap()).unwrap();
+        let params = parse(&capture.data["params"]);
+        check(&parse(&params["data"])["dmViewer_1"]["fields"]["viewerList"]);
+        assert_eq!(params["linkage"], "{}");
+        assert_eq!(capture.data["feature"], "{}");
+    }
+}
@@ -1,14 +1,21 @@
-use std::{env, time::Instant};
-
-use anyhow::Result;
-use log::{debug, warn};
-use reqwest::{
-    header::{HeaderMap, HeaderValue},
-    Client,
+use std::{
+    env,
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
 };
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use log::{debug, info, warn};
+use reqwest::Client;
 use serde_json::{json, Value};
 
-use crate::models::{ticket::TicketInfoParams, DmRes, DmToken};
+use crate::{
+    capture::{Capture, CaptureWriter, ReplayTransport},
+    models::{DmRes, DmToken},
+    report::RequestRecord,
+    transport::{MtopRequest, ReqwestTransport, Transport},
+};
 
 const SUCCESS_CODE: u64 = 200;
 const SYSTEM_ERROR_CODE: u16 = 500;
@@ -25,7 +32,8 @@ impl TokenClient {
 
     // Get value from api.
     pub async fn get_value(&self, key: &str) -> Result<String> {
-        let url = env::var("TOKEN_SERVER_URL").unwrap();
+        let url =
+            env::var("TOKEN_SERVER_URL").map_err(|_| anyhow!("未设置环境变量TOKEN_SERVER_URL"))?;
 
         let params = json!({
             "key": key,
@@ -81,93 +89,88 @@ impl TokenClient {
 }
 
 pub struct DmClient {
-    pub client: Client,
-    pub token_client: TokenClient,
-    pub token: DmToken,
+    pub cookie: String,
     pub bx_token: String,
-}
-
-pub async fn get_token(cookie: &str) -> Result<DmToken> {
-    let mut headers = HeaderMap::new();
-    let url = "https://mtop.damai.cn/";
-
-    headers.append("origin", HeaderValue::from_str(url)?);
-    headers.append("referer", HeaderValue::from_str(url)?);
-    headers.append("cookie", HeaderValue::from_str(cookie)?);
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .cookie_store(true)
-        .http2_prior_knowledge()
-        .build()?;
-
-    let mut token = DmToken {
-        enc_token: "".to_string(),
-        token_with_time: "".to_string(),
-        token: "".to_string(),
-    };
-
-    let url = "https://mtop.damai.cn/h5/mtop.damai.wireless.search.broadcast.list/1.0/?";
-    let params = TicketInfoParams::build()?;
-    let response = client.get(url).form(&params).send().await?;
-
-    for cookie in response.cookies() {
-        if cookie.name() == "_m_h5_tk" {
-            token.token_with_time = cookie.value().to_string();
-            token.token = token.token_with_time.split('_').collect::<Vec<_>>()[0].to_string();
-        }
-        if cookie.name() == "_m_h5_tk_enc" {
-            token.enc_token = cookie.value().to_string();
-        }
-    }
-    Ok(token)
+    pub transport: Arc<dyn Transport>,
+    token: RwLock<DmToken>,
+    records: Mutex<Vec<RequestRecord>>,
+    capture: Option<CaptureWriter>,
 }
 
 impl DmClient {
+    // 设置环境变量MTOP_REPLAY_FILE时回放抓包文件, 不请求大麦网.
     pub async fn new(cookie: String) -> Result<Self> {
-        let token_client = TokenClient::new()?;
-
-        let bx_token = token_client.get_bx_token().await?;
+        let transport: Arc<dyn Transport> = match env::var("MTOP_REPLAY_FILE") {
+            Ok(path) => Arc::new(ReplayTransport::open(&path)?),
+            Err(_) => Arc::new(ReqwestTransport::from_env()?),
+        };
+        Self::with_transport(cookie, transport).await
+    }
 
-        let token = get_token(&cookie).await?;
+    pub async fn with_transport(cookie: String, transport: Arc<dyn Transport>) -> Result<Self> {
+        let bx_token = transport.bx_token().await?;
 
-        let mut headers = HeaderMap::new();
+        let token = transport.fetch_token(&cookie).await?;
 
-        let base_url = "https://mtop.damai.cn/";
-        headers.append("origin", HeaderValue::from_str(base_url)?);
-        headers.append("referer", HeaderValue::from_str(base_url)?);
+        // 设置环境变量MTOP_CAPTURE_FILE时, 将请求和响应追加写入该文件
+        let capture = match env::var("MTOP_CAPTURE_FILE") {
+            Ok(path) => Some(CaptureWriter::open(&path)?),
+            Err(_) => None,
+        };
 
-        headers.append(
-            "cookie",
-            HeaderValue::from_str(
-                format!(
-                    "{};_m_h5_tk_enc={};_m_h5_tk={};",
-                    &cookie, token.enc_token, token.token_with_time
-                )
-                .as_str(),
-            )?,
-        );
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .cookie_store(true)
-            .http2_prior_knowledge()
-            .user_agent("Mozilla/5.0 (iPhone; CPU iPhone OS 13_2_3 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/13.0.3")
-            .use_rustls_tls()
-            .build()?;
         Ok(Self {
-            client,
-            token,
-            token_client,
+            cookie,
             bx_token,
+            transport,
+            token: RwLock::new(token),
+            records: Mutex::new(Vec::new()),
+            capture,
         })
     }
 
-    pub async fn request(&self, url: &str, mut params: Value, data: Value) -> Result<DmRes> {
+    pub fn token(&self) -> DmToken {
+        self.token.read().unwrap().clone()
+    }
+
+    // 取出已发送请求的记录, 用于运行报告.
+    pub fn take_records(&self) -> Vec<RequestRecord> {
+        std::mem::take(&mut *self.records.lock().unwrap())
+    }
+
+    // 重新获取_m_h5_tk.
+    pub async fn refresh_token(&self) -> Result<()> {
+        let start = Instant::now();
+        let token = self.transport.fetch_token(&self.cookie).await?;
+        *self.token.write().unwrap() = token;
+        info!("刷新令牌成功, 花费时间:{:?}", start.elapsed());
+        Ok(())
+    }
+
+    // 发送请求, 令牌过期时刷新令牌并重试一次.
+    pub async fn request(&self, path: &str, params: Value, data: Value) -> Result<DmRes> {
+        let res = self.send(path, params.clone(), &data).await?;
+
+        match res.error() {
+            Some(e) if e.is_token_expired() => {
+                warn!("{}, 正在刷新令牌后重试...", e);
+                self.refresh_token().await?;
+                let mut params = params;
+                params["t"] = Local::now().timestamp_millis().to_string().into();
+                self.send(path, params, &data).await
+            }
+            _ => Ok(res),
+        }
+    }
+
+    async fn send(&self, path: &str, mut params: Value, data: &Value) -> Result<DmRes> {
+        let token = self.token();
+
         let s = format!(
             "{}&{}&{}&{}",
-            self.token.token,
+            token.token,
             params["t"].as_str().unwrap(),
             params["appKey"].as_str().unwrap(),
-            serde_json::to_string(&data)?,
+            serde_json::to_string(data)?,
         );
 
         let sign = format!("{:?}", md5::compute(s));
@@ -175,24 +178,131 @@ impl DmClient {
         params["sign"] = sign.into();
 
         params["bx-umidtoken"] = self.bx_token.clone().into();
-        params["bx-ua"] = self.token_client.get_bx_ua().await?.into();
+        params["bx-ua"] = self.transport.bx_ua().await?.into();
 
         let form = json!({
-            "data": serde_json::to_string(&data)?,
+            "data": serde_json::to_string(data)?,
             // "bx-umidtoken": params["bx-umidtoken"],
             // "bx-ua": params["bx-ua"]
         });
 
-        let response = self
-            .client
-            .post(url)
-            .query(&params)
-            .form(&form)
-            .send()
-            .await?;
+        let cookie = format!(
+            "{};_m_h5_tk_enc={};_m_h5_tk={};",
+            self.cookie, token.enc_token, token.token_with_time
+        );
 
-        let data = response.json::<DmRes>().await?;
+        let api = params["api"].as_str().unwrap_or_default().to_string();
+        let started_at = Local::now().timestamp_millis();
+        let start = Instant::now();
 
-        Ok(data)
+        let request = MtopRequest {
+            path: path.to_string(),
+            params,
+            form,
+            cookie,
+        };
+
+        let res = self.transport.send(request.clone()).await;
+
+        if let Some(capture) = &self.capture {
+            if let Err(e) = capture.write(&Capture::new(&request, &res)) {
+                warn!("写入抓包记录失败, {}", e);
+            }
+        }
+
+        self.records.lock().unwrap().push(RequestRecord {
+            api,
+            started_at,
+            latency: start.elapsed().as_secs_f64() * 1000.0,
+            ret: res.as_ref().map(|r| r.ret.clone()).unwrap_or_default(),
+            error: res.as_ref().err().map(|e| e.to_string()),
+        });
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::transport::fake::{failure, success, FakeTransport};
+
+    const TOKEN_EXPIRED: &str = "FAIL_SYS_TOKEN_EXOIRED::令牌过期";
+
+    // 前expired次请求返回令牌过期
+    async fn client(expired: usize) -> (DmClient, Arc<FakeTransport>) {
+        let count = AtomicUsize::new(0);
+        let transport = Arc::new(FakeTransport::new(move |_, _| {
+            Ok(if count.fetch_add(1, Ordering::SeqCst) < expired {
+                failure(TOKEN_EXPIRED)
+            } else {
+                success(json!({}))
+            })
+        }));
+        let client = DmClient::with_transport("cna=abc".to_string(), transport.clone())
+            .await
+            .unwrap();
+        (client, transport)
+    }
+
+    fn params() -> Value {
+        json!({
+            "api": "mtop.trade.order.build.h5",
+            "appKey": "12574478",
+            "t": "1792324489783",
+        })
+    }
+
+    // 按请求中的令牌计算的签名
+    fn sign(request: &MtopRequest, token: &str) -> String {
+        let s = format!(
+            "{}&{}&{}&{}",
+            token,
+            request.params["t"].as_str().unwrap(),
+            request.params["appKey"].as_str().unwrap(),
+            request.form["data"].as_str().unwrap(),
+        );
+        format!("{:?}", md5::compute(s))
+    }
+
+    #[tokio::test]
+    async fn refresh_token_and_retry() {
+        let (client, transport) = client(1).await;
+
+        let res = client
+            .request("h5/test/", params(), json!({"a": 1}))
+            .await
+            .unwrap();
+        assert!(res.error().is_none(), "{:?}", res.ret);
+        // 创建客户端时获取一次, 过期后刷新一次
+        assert_eq!(transport.token_fetches.load(Ordering::SeqCst), 2);
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].params["sign"], sign(&requests[0], "token1"));
+        assert_eq!(requests[1].params["sign"], sign(&requests[1], "token2"));
+        assert!(
+            requests[1].cookie.contains("_m_h5_tk=token2_"),
+            "{}",
+            requests[1].cookie
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_only_once() {
+        let (client, transport) = client(usize::MAX).await;
+
+        let res = client
+            .request("h5/test/", params(), json!({"a": 1}))
+            .await
+            .unwrap();
+        assert!(res.error().unwrap().is_token_expired(), "{:?}", res.ret);
+        assert_eq!(transport.token_fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            transport.apis(),
+            ["mtop.trade.order.build.h5", "mtop.trade.order.build.h5"]
+        );
     }
 }
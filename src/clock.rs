@@ -0,0 +1,215 @@
+use std::{
+    sync::atomic::{AtomicI64, Ordering},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use log::debug;
+use tokio::{net::UdpSocket, time::timeout};
+
+use crate::transport::Transport;
+
+// NTP时间戳起点(1900-01-01)与unix时间戳起点的秒数差
+pub const NTP_UNIX_OFFSET_SECS: i64 = 2_208_988_800;
+
+// 一次校时结果, 单位毫秒. offset = 服务器时间 - 本地时间
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSample {
+    pub offset: i64,
+    pub rtt: i64,
+}
+
+// 校正后的服务器时钟
+#[derive(Debug, Default)]
+pub struct ServerClock {
+    offset: AtomicI64,
+    rtt: AtomicI64,
+}
+
+impl ServerClock {
+    pub fn set(&self, sample: ClockSample) {
+        self.offset.store(sample.offset, Ordering::SeqCst);
+        self.rtt.store(sample.rtt, Ordering::SeqCst);
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    pub fn rtt(&self) -> i64 {
+        self.rtt.load(Ordering::SeqCst)
+    }
+
+    // 当前服务器时间戳(毫秒)
+    pub fn now_millis(&self) -> i64 {
+        Local::now().timestamp_millis() + self.offset()
+    }
+}
+
+// 一次响应头Date采样, 单位毫秒. t0/t1为发出请求/收到响应时的本地时间
+#[derive(Debug, Clone, Copy)]
+pub struct DateSample {
+    pub t0: i64,
+    pub date: i64,
+    pub t1: i64,
+}
+
+/// 由响应头Date采样估算时钟偏差.
+///
+/// Date只精确到秒, 每次采样得到偏差的区间[date - t1, date + 1000 - t0],
+/// 多次采样取交集缩小误差, 交集为空时退化为各区间中点的中位数.
+pub fn estimate_offset(samples: &[DateSample]) -> Option<ClockSample> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let mut lower = i64::MIN;
+    let mut upper = i64::MAX;
+    let mut mids = Vec::new();
+    let mut rtts = Vec::new();
+    for s in samples {
+        let (lo, hi) = (s.date - s.t1, s.date + 1000 - s.t0);
+        lower = lower.max(lo);
+        upper = upper.min(hi);
+        mids.push((lo + hi) / 2);
+        rtts.push(s.t1 - s.t0);
+    }
+
+    rtts.sort_unstable();
+    let rtt = rtts[rtts.len() / 2];
+
+    let offset = match lower <= upper {
+        true => (lower + upper) / 2,
+        false => {
+            mids.sort_unstable();
+            mids[mids.len() / 2]
+        }
+    };
+    Some(ClockSample { offset, rtt })
+}
+
+/// 通过响应头Date估算时钟偏差, 采样samples次.
+pub async fn sync_http(transport: &dyn Transport, samples: usize) -> Result<ClockSample> {
+    let samples = samples.max(1);
+    let mut dates = Vec::new();
+
+    for i in 0..samples {
+        if i > 0 {
+            // 错开采样时刻在秒内的位置, 使区间交集更小
+            tokio::time::sleep(Duration::from_millis(1000 / samples as u64 + 7)).await;
+        }
+
+        let t0 = Local::now().timestamp_millis();
+        let date = transport.server_date().await?;
+        let t1 = Local::now().timestamp_millis();
+        debug!("校时采样:{}, date:{}, rtt:{}ms", i, date, t1 - t0);
+        dates.push(DateSample { t0, date, t1 });
+    }
+
+    estimate_offset(&dates).ok_or_else(|| anyhow!("没有校时采样"))
+}
+
+fn read_ntp_timestamp(buf: &[u8]) -> i64 {
+    let secs = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as i64;
+    let frac = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) as i64;
+    (secs - NTP_UNIX_OFFSET_SECS) * 1000 + ((frac * 1000) >> 32)
+}
+
+/// 通过SNTP估算时钟偏差, server如: ntp.aliyun.com:123
+pub async fn sync_sntp(server: &str) -> Result<ClockSample> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(server).await?;
+
+    // LI=0, VN=3, Mode=3(client)
+    let mut request = [0u8; 48];
+    request[0] = 0x1b;
+
+    let t1 = Local::now().timestamp_millis();
+    socket.send(&request).await?;
+
+    let mut response = [0u8; 48];
+    let len = timeout(Duration::from_secs(3), socket.recv(&mut response))
+        .await
+        .map_err(|_| anyhow!("NTP服务器:{}响应超时", server))??;
+    let t4 = Local::now().timestamp_millis();
+
+    if len < 48 {
+        return Err(anyhow!("NTP服务器:{}响应数据不完整", server));
+    }
+
+    let t2 = read_ntp_timestamp(&response[32..40]);
+    let t3 = read_ntp_timestamp(&response[40..48]);
+
+    Ok(ClockSample {
+        offset: ((t2 - t1) + (t3 - t4)) / 2,
+        rtt: (t4 - t1) - (t3 - t2),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(t0: i64, date: i64, t1: i64) -> DateSample {
+        DateSample { t0, date, t1 }
+    }
+
+    #[test]
+    fn offset_from_dates() {
+        let cases = [
+            // 单次采样: 区间[date - t1, date + 1000 - t0]的中点
+            (vec![sample(10_000, 10_000, 10_020)], 490, 20),
+            // 区间[-20, 1000]与[580, 1600]的交集为[580, 1000]
+            (
+                vec![
+                    sample(10_000, 10_000, 10_020),
+                    sample(10_400, 11_000, 10_420),
+                ],
+                790,
+                20,
+            ),
+            (
+                vec![
+                    sample(10_000, 10_000, 10_020),
+                    sample(10_620, 11_000, 10_640),
+                    sample(11_240, 12_000, 11_260),
+                ],
+                870,
+                20,
+            ),
+            // 交集为空时取各区间中点的中位数
+            (
+                vec![
+                    sample(10_000, 10_000, 10_010),
+                    sample(20_000, 25_000, 20_010),
+                    sample(30_000, 30_000, 30_030),
+                ],
+                495,
+                10,
+            ),
+        ];
+
+        for (samples, offset, rtt) in cases {
+            let estimated = estimate_offset(&samples).unwrap();
+            assert_eq!(estimated, ClockSample { offset, rtt }, "{:?}", samples);
+        }
+        assert_eq!(estimate_offset(&[]), None);
+    }
+
+    #[test]
+    fn ntp_timestamp() {
+        let cases = [
+            (1_792_324_489, 0u32, 1_792_324_489_000),
+            (1_792_324_489, 1 << 31, 1_792_324_489_500),
+            (1_792_324_489, u32::MAX, 1_792_324_489_999),
+            (0, 0, 0),
+        ];
+        for (unix_secs, frac, expected) in cases {
+            let mut buf = [0u8; 8];
+            buf[..4].copy_from_slice(&((unix_secs + NTP_UNIX_OFFSET_SECS) as u32).to_be_bytes());
+            buf[4..].copy_from_slice(&frac.to_be_bytes());
+            assert_eq!(read_ntp_timestamp(&buf), expected, "{} {}", unix_secs, frac);
+        }
+    }
+}
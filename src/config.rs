@@ -1,56 +1,385 @@
-use log::error;
-use schemars::schema::RootSchema;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt, fs};
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use anyhow::{anyhow, Result};
+use log::info;
+use schemars::{schema::RootSchema, schema_for, JsonSchema};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    item::resolve_item_id,
+    matcher::{GradeMatcher, SessionMatcher},
+    notify::NotifierConfig,
+    payment::PayWatch,
+    retry::RetryPolicy,
+};
+
+/// 备选场次/票档
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct TicketCandidate {
+    /// 场次序号(从1开始), 或场次日期、场次名称关键字、performId
+    pub sessions: SessionMatcher,
+    /// 票档序号(从1开始), 或票档价格、票档名称关键字、skuId
+    pub grade: GradeMatcher,
+}
+
+/// 需要抢购的门票
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Ticket {
+    /// 门票ID, 或门票详情页URL(m.damai.cn/detail.damai.cn)、短链接, 加载配置时转换为itemId
     pub id: String,
+    /// 购票数量
+    #[schemars(range(min = 1))]
     pub num: usize,
-    pub sessions: usize,
-    pub grade: usize,
+    /// 场次序号(从1开始), 或场次日期(如: "2026-11-02")、场次名称关键字、performId
+    pub sessions: SessionMatcher,
+    /// 票档序号(从1开始), 或票档价格(如: "680")、票档名称关键字、skuId
+    pub grade: GradeMatcher,
+    /// 备选场次/票档, 当前场次/票档售罄时按顺序尝试
+    pub fallbacks: Option<Vec<TicketCandidate>>,
+}
+
+impl Ticket {
+    // 按优先级排列的场次/票档, 首个为sessions/grade
+    pub fn candidates(&self) -> Vec<TicketCandidate> {
+        let mut candidates = vec![TicketCandidate {
+            sessions: self.sessions.clone(),
+            grade: self.grade.clone(),
+        }];
+        candidates.extend(self.fallbacks.clone().unwrap_or_default());
+        candidates
+    }
+}
+
+/// 实名观演人少于购票数量时的处理方式
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewerPolicy {
+    /// 终止抢购
+    #[default]
+    Abort,
+    /// 按实名观演人数量购票
+    BuyFewer,
+    /// 忽略, 继续提交订单
+    Proceed,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// 抢票账号
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
 pub struct Account {
+    /// .damai.cn中的cookie完整字符串
     pub cookie: String,
+    /// 账号备注, 用于日志、通知和运行报告
     pub remark: String,
     pub ticket: Ticket,
+    /// 倒计时刷新间隔(毫秒), 默认200
+    #[schemars(range(min = 10, max = 60000))]
     pub interval: Option<u64>,
+    /// 最早提前多少毫秒发包, 默认1
     pub earliest_submit_time: Option<i64>,
+    /// 开抢前多少毫秒开始预热连接, 0为不预热, 默认3000
+    pub prewarm_time: Option<i64>,
+    /// 试运行, 只生成订单不提交
+    pub dry_run: Option<bool>,
+    /// 实名观演人姓名或证件号后4位, 数量须与ticket.num一致, 不填写时按顺序选择
+    pub viewers: Option<Vec<String>>,
+    /// 实名观演人少于购票数量时的处理方式, 默认abort. 填写viewers时不能使用buy_fewer
+    pub viewer_policy: Option<ViewerPolicy>,
+    /// 校时使用的NTP服务器, 如: ntp.aliyun.com:123, 不填写时通过响应头Date校时
+    pub ntp_server: Option<String>,
+    /// 抢购重试策略
+    pub retry: Option<RetryPolicy>,
+    /// 下单成功后监控订单付款状态, 不填写则不监控
+    pub pay_watch: Option<PayWatch>,
+    /// 抢票结果通知
+    pub notifiers: Option<Vec<NotifierConfig>>,
+    /// 运行报告目录, 默认./reports
+    pub report_dir: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// dm-ticket配置文件
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct Config {
+    /// 抢票账号列表
     pub accounts: Vec<Account>,
 }
 
-fn load_config<T>(path: &str) -> Option<T>
-where
-    T: DeserializeOwned,
-{
-    // 1.通过std::fs读取配置文件内容
-    // 2.通过serde_yaml解析读取到的yaml配置转换成json对象
-    match serde_yaml::from_str::<RootSchema>(
-        &std::fs::read_to_string(path).unwrap_or_else(|_| panic!("failure read file {}", path)),
-    ) {
-        Ok(root_schema) => {
-            // 通过serde_json把json对象转换指定的model
-            let data =
-                serde_json::to_string_pretty(&root_schema).expect("failure to parse RootSchema");
-            let config = serde_json::from_str::<T>(&data)
-                .unwrap_or_else(|_| panic!("failure to format json str {}", &data));
-            // 返回格式化结果
-            Some(config)
+// 配置文件的JSON Schema, 用于编辑器补全和CI校验
+pub fn schema() -> RootSchema {
+    schema_for!(Config)
+}
+
+// 默认配置文件路径
+pub const DEFAULT_CONFIG_PATH: &str = "./config/config.yaml";
+
+// 配置校验发现的所有问题
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub path: String,
+    pub problems: Vec<String>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "配置文件:{}存在{}个问题:",
+            self.path,
+            self.problems.len()
+        )?;
+        for problem in self.problems.iter() {
+            write!(f, "\n\t- {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// 校验场次/票档序号, 序号从1开始
+fn check_candidate(problems: &mut Vec<String>, name: &str, candidate: &TicketCandidate) {
+    if candidate.sessions == SessionMatcher::Index(0) {
+        problems.push(format!("{}sessions序号从1开始, 不能为0", name));
+    }
+    if candidate.grade == GradeMatcher::Index(0) {
+        problems.push(format!("{}grade序号从1开始, 不能为0", name));
+    }
+}
+
+impl Account {
+    // 校验账号配置, 返回所有问题
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.cookie.trim().is_empty() {
+            problems.push("cookie不能为空".to_string());
+        }
+        if self.ticket.id.trim().is_empty() {
+            problems.push("ticket.id不能为空".to_string());
+        }
+        if self.ticket.num < 1 {
+            problems.push("ticket.num不能小于1".to_string());
+        }
+
+        for (i, candidate) in self.ticket.candidates().iter().enumerate() {
+            let name = match i {
+                0 => "ticket.".to_string(),
+                i => format!("ticket.fallbacks[{}].", i - 1),
+            };
+            check_candidate(&mut problems, &name, candidate);
+        }
+
+        if let Some(interval) = self.interval {
+            if !(10..=60_000).contains(&interval) {
+                problems.push(format!("interval:{}应在10~60000毫秒之间", interval));
+            }
+        }
+
+        if let Some(retry) = &self.retry {
+            if retry.max_attempts < 1 {
+                problems.push("retry.max_attempts不能小于1".to_string());
+            }
+        }
+
+        if let Some(viewers) = &self.viewers {
+            if viewers.iter().any(|v| v.trim().is_empty()) {
+                problems.push("viewers不能包含空字符串".to_string());
+            }
+            if self.viewer_policy == Some(ViewerPolicy::BuyFewer) {
+                problems.push(
+                    "viewers与viewer_policy: buy_fewer不能同时使用, 指定实名观演人时购票数量须与viewers一致"
+                        .to_string(),
+                );
+            }
+            if viewers.len() != self.ticket.num {
+                problems.push(format!(
+                    "viewers数量:{}与购票数量ticket.num:{}不一致",
+                    viewers.len(),
+                    self.ticket.num
+                ));
+            }
+        }
+
+        for (i, notifier) in self.notifiers.iter().flatten().enumerate() {
+            if let Err(e) = notifier.build() {
+                problems.push(format!("notifiers[{}]配置错误, {}", i, e));
+            }
+        }
+
+        problems
+    }
+}
+
+// 问题描述中使用的账号名称, 未填写备注时使用序号
+fn account_label(i: usize, account: &Account) -> String {
+    match account.remark.trim() {
+        "" => format!("第{}个账号", i + 1),
+        remark => remark.to_string(),
+    }
+}
+
+impl Config {
+    // 校验所有账号, 问题前加上账号备注
+    pub fn validate(&self) -> Vec<String> {
+        if self.accounts.is_empty() {
+            return vec!["accounts不能为空".to_string()];
+        }
+
+        let mut problems = Vec::new();
+        for (i, account) in self.accounts.iter().enumerate() {
+            let label = account_label(i, account);
+            problems.extend(
+                account
+                    .validate()
+                    .into_iter()
+                    .map(|p| format!("账号:{}, {}", label, p)),
+            );
+        }
+        problems
+    }
+
+    // 将ticket.id中的门票链接转换为itemId
+    pub async fn normalize_item_ids(&mut self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (i, account) in self.accounts.iter_mut().enumerate() {
+            if account.ticket.id.trim().is_empty() {
+                continue;
+            }
+            match resolve_item_id(&account.ticket.id).await {
+                Ok(id) => {
+                    if id != account.ticket.id {
+                        info!(
+                            "账号:{}, ticket.id {} -> itemId:{}",
+                            account_label(i, account),
+                            account.ticket.id.trim(),
+                            id
+                        );
+                    }
+                    account.ticket.id = id;
+                }
+                Err(e) => problems.push(format!(
+                    "账号:{}, ticket.id {}",
+                    account_label(i, account),
+                    e
+                )),
+            }
         }
-        Err(err) => {
-            // 记录日志
-            error!("{}", err);
-            // 返回None
-            None
+        problems
+    }
+}
+
+// 读取并校验配置文件, 格式错误时返回所在行列
+pub async fn load_config(path: &str) -> Result<Config> {
+    let content =
+        fs::read_to_string(path).map_err(|e| anyhow!("读取配置文件:{}失败, {}", path, e))?;
+
+    let mut config = serde_yaml::from_str::<Config>(&content)
+        .map_err(|e| anyhow!("解析配置文件:{}失败, {}", path, e))?;
+
+    let mut problems = config.validate();
+    problems.extend(config.normalize_item_ids().await);
+    if !problems.is_empty() {
+        return Err(ConfigError {
+            path: path.to_string(),
+            problems,
         }
+        .into());
     }
+    Ok(config)
+}
+
+pub async fn load_global_config() -> Result<Config> {
+    load_config(DEFAULT_CONFIG_PATH).await
 }
 
-pub fn load_global_config() -> Option<Config> {
-    load_config("./config/config.yaml")
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+accounts:
+  - cookie: "cna=abc"
+    remark: 账号1
+    interval: 5
+    viewers: ["张三"]
+    ticket:
+      id: "710947802955"
+      num: 0
+      sessions: 0
+      grade: 0
+  - cookie: "cna=def"
+    remark: ""
+    viewers: ["张三", "李四"]
+    ticket:
+      id: "710947802955"
+      num: 1
+      sessions: 1
+      grade: 1
+"#;
+
+    // 写入临时配置文件, 返回路径
+    fn write_config(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(format!("dm-ticket-{}.yaml", name));
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[tokio::test]
+    async fn report_all_problems() {
+        let path = write_config("problems", CONFIG);
+        let e = load_config(&path).await.unwrap_err();
+        let e = e.downcast_ref::<ConfigError>().unwrap();
+
+        assert_eq!(
+            e.problems,
+            [
+                "账号:账号1, ticket.num不能小于1",
+                "账号:账号1, ticket.sessions序号从1开始, 不能为0",
+                "账号:账号1, ticket.grade序号从1开始, 不能为0",
+                "账号:账号1, interval:5应在10~60000毫秒之间",
+                "账号:账号1, viewers数量:1与购票数量ticket.num:0不一致",
+                "账号:第2个账号, viewers数量:2与购票数量ticket.num:1不一致",
+            ]
+        );
+        assert!(e.to_string().contains("存在6个问题"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn valid_config() {
+        let content = r#"
+accounts:
+  - cookie: "cna=ghi"
+    remark: 账号3
+    interval: 200
+    ticket:
+      id: "https://m.damai.cn/damai/detail/item.html?itemId=710947802955"
+      num: 2
+      sessions: "2026-11-02"
+      grade: 680
+"#;
+        let path = write_config("valid", content);
+        let config = load_config(&path).await.unwrap();
+        assert_eq!(config.accounts.len(), 1);
+        assert_eq!(config.accounts[0].ticket.id, "710947802955");
+    }
+
+    #[tokio::test]
+    async fn syntax_error() {
+        let path = write_config(
+            "syntax",
+            "accounts:\n  - cookie: \"cna=abc\n    remark: [\n",
+        );
+        let e = load_config(&path).await.unwrap_err().to_string();
+        assert!(
+            e.starts_with(&format!("解析配置文件:{}失败", path)),
+            "{}",
+            e
+        );
+        assert!(e.contains("line 2 column"), "{}", e);
+    }
+
+    #[test]
+    fn empty_accounts() {
+        let config = serde_yaml::from_str::<Config>("accounts: []").unwrap();
+        assert_eq!(config.validate(), ["accounts不能为空"]);
+    }
 }
@@ -1,29 +1,64 @@
 use std::{
-    io::{self, Write},
+    collections::{HashMap, HashSet},
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     time::{Duration, Instant},
 };
 
 use crate::{
     client::DmClient,
-    config::Account,
+    clock::{sync_http, sync_sntp, ServerClock},
+    config::{Account, ViewerPolicy},
+    error::DmError,
+    inspect::{ItemOverview, SkuOverview},
+    matcher::{describe_viewer, match_viewers, session_index},
     models::{
-        order::{OrderForm, OrderInfo, OrderParams, SubmitOrderParams},
+        order::{
+            parse_order_id, OrderDetailForm, OrderDetailParams, OrderForm, OrderInfo, OrderParams,
+            OrderSummary, SubmitOrderParams,
+        },
         perform::{PerformForm, PerformInfo, PerformParams},
         ticket::{TicketInfo, TicketInfoForm, TicketInfoParams},
         DmRes,
     },
+    notify::{Notification, Notifiers, NotifyEvent},
+    payment::{report_payment, watch_payment, PayWatch},
+    report::{RunOutcome, RunReport, StepRecord, DEFAULT_REPORT_DIR},
+    retry::{ErrorClass, RetryAction},
+    scheduler::{countdown, wait_until},
 };
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Local};
+use chrono::Local;
+use futures::future;
 use log::{debug, error, info, warn};
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{json, Value};
 use tokio::signal;
 
-const SUCCESS_FLAG: &str = "SUCCESS::调用成功";
-
 pub struct DmTicket {
     pub client: DmClient,
     pub account: Account,
+    pub clock: ServerClock,
+    // 实际购票数量, 实名观演人不足且策略为buy_fewer时减少
+    num: AtomicUsize,
+    // 提交失败但仍可复用的订单, (skuId, 订单)
+    pending_order: Mutex<Option<(String, OrderInfo)>>,
+    notifiers: Arc<Notifiers>,
+    report: Mutex<RunReport>,
+}
+
+// 解析后的候选场次/票档
+#[derive(Serialize, Debug, Clone)]
+pub struct BuyTarget {
+    pub perform_id: String,
+    pub perform_name: String,
+    pub item_id: String,
+    pub sku_id: String,
+    pub sku_name: String,
+    pub price: String,
 }
 
 impl DmTicket {
@@ -39,30 +74,54 @@ impl DmTicket {
 
         let client = DmClient::new(cookie).await?;
 
-        Ok(Self { client, account })
+        Ok(Self::with_client(account, client))
+    }
+
+    // 使用已创建的客户端, 如请求本地mock服务的DmClient.
+    pub fn with_client(account: Account, client: DmClient) -> Self {
+        let num = AtomicUsize::new(account.ticket.num);
+        let report = Mutex::new(RunReport::new(&account.remark, &account.ticket.id));
+        Self {
+            client,
+            account,
+            clock: ServerClock::default(),
+            num,
+            pending_order: Mutex::new(None),
+            notifiers: Arc::new(Notifiers::default()),
+            report,
+        }
+    }
+
+    pub fn with_notifiers(mut self, notifiers: Arc<Notifiers>) -> Self {
+        self.notifiers = notifiers;
+        self
+    }
+
+    pub fn num(&self) -> usize {
+        self.num.load(Ordering::SeqCst)
     }
 
     // 获取门票信息
     pub async fn get_ticket_info(&self, ticket_id: String) -> Result<TicketInfo> {
-        let url = "https://mtop.damai.cn/h5/mtop.alibaba.damai.detail.getdetail/1.2";
+        let path = "h5/mtop.alibaba.damai.detail.getdetail/1.2";
 
         let params = TicketInfoParams::build()?;
 
         let data = TicketInfoForm::build(ticket_id)?;
 
-        let res = self.client.request(url, params, data).await?;
+        let res = self.client.request(path, params, data).await?;
 
-        match res.ret.contains(&SUCCESS_FLAG.to_string()) {
-            true => {
+        match res.error() {
+            None => {
                 debug!("获取门票信息成功, {:?}", res);
 
                 let ticket_info: TicketInfo =
                     serde_json::from_str(res.data["result"].clone().as_str().unwrap())?;
                 Ok(ticket_info)
             }
-            false => {
+            Some(e) => {
                 error!("获取门票信息失败, 结果:{:?}", res.ret);
-                Err(anyhow!("获取门票信息失败..."))
+                Err(e.into())
             }
         }
     }
@@ -71,41 +130,47 @@ impl DmTicket {
     pub async fn build_order(&self, item_id: &String, sku_id: &String) -> Result<OrderInfo> {
         let start = Instant::now();
 
-        let url = "https://mtop.damai.cn/h5/mtop.trade.order.build.h5/4.0/?";
+        let path = "h5/mtop.trade.order.build.h5/4.0/?";
 
         let params = OrderParams::build()?;
 
-        let data = OrderForm::build(item_id, sku_id, self.account.ticket.num)?;
+        let data = OrderForm::build(item_id, sku_id, self.num())?;
 
-        let res = self.client.request(url, params, data).await?;
+        let res = self.client.request(path, params, data).await?;
 
         debug!("生成订单结果:{:?}, 花费时间:{:?}", res, start.elapsed());
 
-        match res.ret.contains(&SUCCESS_FLAG.to_string()) {
-            true => {
+        match res.error() {
+            None => {
                 let order_info: OrderInfo = serde_json::from_value(res.data)?;
                 Ok(order_info)
             }
-            false => Err(anyhow!("{:?}", res.ret)),
+            Some(e) => Err(e.into()),
         }
     }
 
-    // 提交订单
-    pub async fn submit_order(&self, order_info: OrderInfo) -> Result<DmRes> {
-        let start = Instant::now();
-
-        let url = "https://mtop.damai.cn/h5/mtop.trade.order.create.h5/4.0/";
-
-        // 添加提交订单需要的数据
+    // 生成提交订单的data, 并勾选实名观演人
+    pub fn build_order_data(&self, order_info: &OrderInfo) -> Result<Value> {
         let mut order_data = json!({});
 
         for key in order_info.linkage.input.iter() {
             if key.starts_with("dmViewer_") {
                 let mut item = order_info.data[key].clone();
-                let mut num = self.account.ticket.num;
+                let mut num = self.num();
 
                 let viewer_list = item["fields"]["viewerList"].clone();
 
+                // 按配置选择实名观演人
+                if let (Some(viewers), Some(list)) = (&self.account.viewers, viewer_list.as_array())
+                {
+                    self.check_viewer_count()?;
+                    for i in match_viewers(viewers, list)? {
+                        item["fields"]["viewerList"][i]["isUsed"] = true.into();
+                    }
+                    order_data[key] = item;
+                    continue;
+                }
+
                 // 需选择实名观演人
                 if viewer_list.is_array() && !viewer_list.as_array().unwrap().is_empty() {
                     // 实名观演人比购票数量少
@@ -135,7 +200,15 @@ impl DmTicket {
                 order_data[s] = order_info.data[s].clone();
             }
         }
+        Ok(order_data)
+    }
 
+    // 生成提交订单的请求参数和表单
+    pub fn build_submit_order(
+        &self,
+        order_info: &OrderInfo,
+        order_data: &Value,
+    ) -> Result<(Value, Value)> {
         let order_hierarchy = json!({
             "structure": order_info.hierarchy.structure
         });
@@ -149,7 +222,7 @@ impl DmTicket {
             "signature": order_info.linkage.signature,
         });
 
-        let submit_order_params = SubmitOrderParams::build(order_info.global.secret_value)?;
+        let submit_order_params = SubmitOrderParams::build(order_info.global.secret_value.clone())?;
 
         let feature = json!({
             "subChannel": "damai@damaih5_h5",
@@ -158,7 +231,7 @@ impl DmTicket {
             "dataTags": "sqm:dianying.h5.unknown.value"
         });
         let params = json!({
-            "data": serde_json::to_string(&order_data)?,
+            "data": serde_json::to_string(order_data)?,
             "hierarchy": serde_json::to_string(&order_hierarchy)?,
             "linkage": serde_json::to_string(&order_linkage)?,
         });
@@ -166,16 +239,154 @@ impl DmTicket {
             "params": serde_json::to_string(&params)?,
             "feature": serde_json::to_string(&feature)?,
         });
+        Ok((submit_order_params, sumbit_order_data))
+    }
+
+    // 提交订单
+    pub async fn submit_order(&self, order_info: &OrderInfo) -> Result<DmRes> {
+        let start = Instant::now();
+
+        let path = "h5/mtop.trade.order.create.h5/4.0/";
+
+        // 添加提交订单需要的数据
+        let order_data = self.build_order_data(order_info)?;
+
+        let (submit_order_params, sumbit_order_data) =
+            self.build_submit_order(order_info, &order_data)?;
 
         let res = self
             .client
-            .request(url, submit_order_params, sumbit_order_data)
+            .request(path, submit_order_params, sumbit_order_data)
             .await?;
 
         debug!("提交订单结果:{:?}, 花费时间:{:?}", res, start.elapsed());
         Ok(res)
     }
 
+    // 指定的实名观演人数量须与购票数量一致, 否则提交订单会被拒绝
+    fn check_viewer_count(&self) -> Result<()> {
+        match &self.account.viewers {
+            Some(viewers) if viewers.len() != self.num() => Err(anyhow!(
+                "指定的实名观演人数量:{}与购票数量:{}不一致",
+                viewers.len(),
+                self.num()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // 检查实名观演人数量, 返回需要重新生成订单的购票数量
+    pub fn check_viewers(&self, order_info: &OrderInfo) -> Result<Option<usize>> {
+        let num = self.num();
+        self.check_viewer_count()?;
+
+        let viewer_list = order_info
+            .linkage
+            .input
+            .iter()
+            .filter(|key| key.starts_with("dmViewer_"))
+            .find_map(|key| order_info.data[key]["fields"]["viewerList"].as_array());
+
+        // 指定了实名观演人时, 检查是否都能在观演人列表中找到
+        if let (Some(viewers), Some(list)) = (&self.account.viewers, viewer_list) {
+            match_viewers(viewers, list)?;
+            return Ok(None);
+        }
+
+        // 无需实名或观演人充足
+        let viewer_num = match viewer_list.map(|list| list.len()) {
+            Some(n) if n < num => n,
+            _ => return Ok(None),
+        };
+
+        let msg = format!(
+            "实名观演人数量:{}小于购票数量:{}, 请先添加实名观演人!",
+            viewer_num, num
+        );
+        match self.account.viewer_policy.clone().unwrap_or_default() {
+            ViewerPolicy::Abort => Err(anyhow!(msg)),
+            ViewerPolicy::BuyFewer if viewer_num > 0 => {
+                warn!("{} 购票数量调整为:{}", msg, viewer_num);
+                self.num.store(viewer_num, Ordering::SeqCst);
+                Ok(Some(viewer_num))
+            }
+            ViewerPolicy::BuyFewer => Err(anyhow!(msg)),
+            ViewerPolicy::Proceed => {
+                warn!("{} 继续提交订单", msg);
+                Ok(None)
+            }
+        }
+    }
+
+    // 生成订单并检查实名观演人, 按策略减少购票数量后重新生成订单
+    pub async fn build_checked_order(&self, target: &BuyTarget) -> Result<OrderInfo> {
+        let order_info = self.build_order(&target.item_id, &target.sku_id).await?;
+        match self.check_viewers(&order_info)? {
+            Some(_) => self.build_order(&target.item_id, &target.sku_id).await,
+            None => Ok(order_info),
+        }
+    }
+
+    // 启动时预生成订单, 提前检查实名观演人. 未开售等原因生成失败时跳过
+    pub async fn preflight(&self, target: &BuyTarget) -> Result<()> {
+        match self.build_order(&target.item_id, &target.sku_id).await {
+            Ok(order_info) => {
+                self.check_viewers(&order_info)?;
+                info!("预检通过, 实名观演人数量充足.");
+            }
+            Err(e) => warn!(
+                "预生成订单失败, 跳过实名观演人预检, 将在开售后生成订单时再检查, {}",
+                e
+            ),
+        }
+        Ok(())
+    }
+
+    // 试运行: 生成订单并打印将要提交的数据, 不提交订单
+    pub async fn dry_run(&self, target: &BuyTarget) -> Result<()> {
+        info!("试运行模式, 正在生成订单...");
+        let order_info = match self.build_checked_order(target).await {
+            Ok(data) => data,
+            Err(e) => {
+                error!("试运行生成订单失败, {}", e);
+                return Err(e);
+            }
+        };
+
+        let order_data = self.build_order_data(&order_info)?;
+        let (params, data) = self.build_submit_order(&order_info, &order_data)?;
+
+        let mut viewers = Vec::new();
+        for key in order_info.linkage.input.iter() {
+            if !key.starts_with("dmViewer_") {
+                continue;
+            }
+            if let Some(list) = order_data[key]["fields"]["viewerList"].as_array() {
+                viewers.extend(
+                    list.iter()
+                        .filter(|v| v["isUsed"].as_bool().unwrap_or(false))
+                        .map(describe_viewer),
+                );
+            }
+        }
+
+        println!(
+            "\r\n\t账号备注:{}\n\t票档名称:{}\n\t票档价格:{}\n\t购买数量:{}\n\t实名观演人:{:?}\n",
+            self.account.remark,
+            target.sku_name,
+            target.price,
+            self.num(),
+            viewers
+        );
+        println!(
+            "\t提交订单参数:{}\n\t提交订单数据:{}\n",
+            serde_json::to_string_pretty(&params)?,
+            serde_json::to_string_pretty(&data)?
+        );
+        info!("试运行结束, 未提交订单.");
+        Ok(())
+    }
+
     pub async fn get_perform_info(
         &self,
         ticket_id: String,
@@ -183,72 +394,533 @@ impl DmTicket {
     ) -> Result<PerformInfo> {
         let start = Instant::now();
 
-        let url = "https://mtop.damai.cn/h5/mtop.alibaba.detail.subpage.getdetail/2.0/";
+        let path = "h5/mtop.alibaba.detail.subpage.getdetail/2.0/";
 
         let params = PerformParams::build()?;
 
         let data = PerformForm::build(ticket_id, perform_id)?;
 
-        let res = self.client.request(url, params, data).await?;
+        let res = self.client.request(path, params, data).await?;
 
         debug!("获取演出票档信息:{:?}, 花费时间:{:?}", res, start.elapsed());
 
+        if let Some(e) = res.error() {
+            error!("获取演出票档信息失败, 结果:{:?}", res.ret);
+            return Err(e.into());
+        }
+
         let perform_info: PerformInfo = serde_json::from_str(res.data["result"].as_str().unwrap())?;
 
         Ok(perform_info)
     }
 
-    pub async fn buy(&self, item_id: &String, sku_id: &String) -> Result<bool> {
+    // 按顺序尝试候选场次/票档, 售罄时尝试下一个. 失败时返回最后一个候选的错误.
+    // sold_out记录已售罄候选的skuId, 重试时跳过, 全部售罄时重新尝试所有候选
+    pub async fn attempt(
+        &self,
+        targets: &[BuyTarget],
+        sold_out: &mut HashSet<String>,
+    ) -> Result<DmRes> {
         let start = Instant::now();
 
-        let order_info = match self.build_order(item_id, sku_id).await {
-            Ok(data) => {
-                info!("成功生成订单...");
-                data
+        if targets.iter().all(|t| sold_out.contains(&t.sku_id)) {
+            sold_out.clear();
+        }
+
+        // 上次提交失败的订单仍可复用时, 直接从该候选开始. 不在候选中时丢弃
+        let pending =
+            self.pending_order.lock().unwrap().take().and_then(
+                |(sku_id, order_info)| match targets.iter().position(|t| t.sku_id == sku_id) {
+                    Some(i) => Some((i, order_info)),
+                    None => {
+                        warn!("已生成订单的票档:{}不在候选中, 重新生成订单", sku_id);
+                        None
+                    }
+                },
+            );
+        let first = pending.as_ref().map(|(i, _)| *i).unwrap_or(0);
+        let mut pending = pending.map(|(_, order_info)| order_info);
+
+        for (i, target) in targets.iter().enumerate().skip(first) {
+            if pending.is_none() && sold_out.contains(&target.sku_id) {
+                debug!("跳过已售罄的{}/{}", target.perform_name, target.sku_name);
+                continue;
             }
+
+            let order_info = match pending.take() {
+                Some(order_info) => {
+                    info!(
+                        "复用已生成的订单, {}/{}...",
+                        target.perform_name, target.sku_name
+                    );
+                    order_info
+                }
+                None => {
+                    let order_info = match self.build_order(&target.item_id, &target.sku_id).await {
+                        Ok(data) => {
+                            info!(
+                                "成功生成订单, {}/{}...",
+                                target.perform_name, target.sku_name
+                            );
+                            data
+                        }
+                        Err(e) => {
+                            info!(
+                                "生成订单失败, {}/{}, {}",
+                                target.perform_name, target.sku_name, e
+                            );
+                            if is_sold_out(&e) {
+                                sold_out.insert(target.sku_id.clone());
+                                if has_next(targets, i, sold_out) {
+                                    continue;
+                                }
+                            }
+                            return Err(e);
+                        }
+                    };
+
+                    // 实名观演人不足时按策略处理, 减少购票数量后重新生成订单
+                    match self.check_viewers(&order_info)? {
+                        Some(_) => self.build_order(&target.item_id, &target.sku_id).await?,
+                        None => order_info,
+                    }
+                }
+            };
+
+            match self.submit(target, order_info, start).await {
+                Err(e) if is_sold_out(&e) => {
+                    sold_out.insert(target.sku_id.clone());
+                    if !has_next(targets, i, sold_out) {
+                        return Err(e);
+                    }
+                }
+                res => return res,
+            }
+        }
+        Err(anyhow!("没有可抢购的场次/票档"))
+    }
+
+    // 提交订单. 网络错误或限流时保留订单, 下次重试直接提交
+    async fn submit(
+        &self,
+        target: &BuyTarget,
+        order_info: OrderInfo,
+        start: Instant,
+    ) -> Result<DmRes> {
+        let res = match self.submit_order(&order_info).await {
+            Ok(res) => res,
             Err(e) => {
-                info!("生成订单失败, {}", e);
-                return Ok(false);
+                if ErrorClass::of(&e) == ErrorClass::Network {
+                    self.keep_order(target, order_info);
+                }
+                return Err(e);
             }
         };
 
-        let res = self.submit_order(order_info).await?;
-
-        match res.ret.contains(&SUCCESS_FLAG.to_string()) {
-            true => {
+        match res.error() {
+            None => {
                 info!(
-                    "提交订单成功, 请尽快前往手机APP付款,  此次抢购花费时间:{:?}",
+                    "提交订单成功, 场次:{}, 票档:{}, 请尽快前往手机APP付款,  此次抢购花费时间:{:?}",
+                    target.perform_name,
+                    target.sku_name,
                     start.elapsed()
                 );
-                Ok(true)
+                Ok(res)
             }
-            false => {
+            Some(e) => {
                 info!(
                     "提交订单失败, 原因:{}, 此次抢购花费时间:{:?}",
-                    res.ret[0],
+                    e,
+                    start.elapsed()
+                );
+                if e.is_throttled() {
+                    self.keep_order(target, order_info);
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    fn keep_order(&self, target: &BuyTarget, order_info: OrderInfo) {
+        *self.pending_order.lock().unwrap() = Some((target.sku_id.clone(), order_info));
+    }
+
+    // 抢购最终失败, 记录到运行报告并发送通知
+    async fn fail(&self, event: NotifyEvent, message: String) {
+        self.report
+            .lock()
+            .unwrap()
+            .finish(RunOutcome::Failure, Some(message.clone()));
+        self.notifiers
+            .notify(Notification::new(event, &self.account.remark, message))
+            .await;
+    }
+
+    // 按重试策略抢购, 成功时返回提交订单的结果
+    pub async fn buy(&self, targets: &[BuyTarget]) -> Result<Option<DmRes>> {
+        let policy = self.account.retry.clone().unwrap_or_default();
+        let start = Instant::now();
+        let mut throttled = 0;
+        let mut sold_out = HashSet::new();
+
+        for attempt in 1.. {
+            let e = match self.attempt(targets, &mut sold_out).await {
+                Ok(res) => return Ok(Some(res)),
+                Err(e) => e,
+            };
+
+            let class = ErrorClass::of(&e);
+            if class == ErrorClass::Throttled {
+                throttled += 1;
+            } else {
+                throttled = 0;
+            }
+
+            if policy.action(class) == RetryAction::Stop {
+                match class {
+                    ErrorClass::Fatal => {
+                        error!("抢购失败, {}", e);
+                        self.fail(NotifyEvent::Failure, e.to_string()).await;
+                        return Err(e);
+                    }
+                    ErrorClass::SessionInvalid => {
+                        error!("抢购失败, {}, 请更新cookie.", e);
+                        self.fail(NotifyEvent::CookieExpired, e.to_string()).await;
+                        return Ok(None);
+                    }
+                    _ => {
+                        info!("抢购失败, {}, 停止抢购.", e);
+                        self.fail(NotifyEvent::Failure, e.to_string()).await;
+                        return Ok(None);
+                    }
+                }
+            }
+
+            if attempt >= policy.max_attempts || start.elapsed() >= policy.deadline() {
+                info!(
+                    "已尝试{}次, 花费时间:{:?}, 停止抢购.",
+                    attempt,
+                    start.elapsed()
+                );
+                let message = format!(
+                    "已尝试{}次, 花费时间:{:?}, 最后一次失败原因:{}",
+                    attempt,
+                    start.elapsed(),
+                    e
+                );
+                self.fail(NotifyEvent::Failure, message).await;
+                return Ok(None);
+            }
+
+            let delay = policy.delay(class, throttled);
+            debug!("第{}次抢购失败, {:?}后重试...", attempt, delay);
+            tokio::time::sleep(delay).await;
+        }
+        Ok(None)
+    }
+
+    // 查询订单详情
+    pub async fn get_order_summary(&self, order_id: &str) -> Result<OrderSummary> {
+        let start = Instant::now();
+
+        let path = "h5/mtop.damai.wireless.order.orderdetail/2.0/";
+
+        let params = OrderDetailParams::build()?;
+
+        let data = OrderDetailForm::build(order_id)?;
+
+        let res = self.client.request(path, params, data).await?;
+
+        debug!("查询订单详情:{:?}, 花费时间:{:?}", res, start.elapsed());
+
+        match res.error() {
+            None => {
+                let summary = OrderSummary::from_detail(order_id, &res.data);
+                if summary.is_empty() {
+                    warn!(
+                        "订单:{} 详情中未解析到任何字段, 接口返回结构可能已变化, 订单状态未知",
+                        order_id
+                    );
+                }
+                Ok(summary)
+            }
+            Some(e) => Err(e.into()),
+        }
+    }
+
+    async fn notify_success(&self, order_id: &str) {
+        let message = format!("提交订单成功, 订单号:{}, 请尽快前往手机APP付款.", order_id);
+        let mut notification =
+            Notification::new(NotifyEvent::Success, &self.account.remark, message);
+        if !order_id.is_empty() {
+            notification = notification.with_order(order_id);
+        }
+        self.notifiers.notify(notification).await;
+    }
+
+    // 查询订单详情后监控付款状态, 直到付款、订单关闭或CTRL-C
+    async fn watch_order(&self, order_id: &str, watch: &PayWatch) {
+        let summary = match self
+            .step("get_order_summary", self.get_order_summary(order_id))
+            .await
+        {
+            Ok(summary) => summary,
+            Err(e) => {
+                warn!(
+                    "查询订单:{}失败, 无法监控付款状态, 请前往手机APP查看, {}",
+                    order_id, e
+                );
+                return;
+            }
+        };
+        self.save_report();
+        tokio::select! {
+            _ = signal::ctrl_c() => {
+                info!("CTRL-C, 停止监控订单:{}付款状态...", order_id);
+            }
+            state = watch_payment(self, &self.notifiers, summary, watch) => {
+                report_payment(&self.notifiers, &self.account.remark, order_id, state).await;
+            }
+        }
+    }
+
+    // 检查cookie是否有效. 生成订单接口需要登录, 未开售、售罄等业务错误说明登录有效,
+    // 被限流时无法判断登录状态.
+    pub async fn check_login(&self) -> Result<()> {
+        let ticket_id = self.account.ticket.id.clone();
+        let ticket_info = self.get_ticket_info(ticket_id.clone()).await?;
+        let targets = self.resolve_targets(&ticket_id, &ticket_info).await?;
+
+        match self
+            .build_order(&targets[0].item_id, &targets[0].sku_id)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => match ErrorClass::of(&e) {
+                ErrorClass::Business | ErrorClass::SoldOut => Ok(()),
+                ErrorClass::Throttled => Err(anyhow!("请求被限流, 无法判断登录状态, {}", e)),
+                ErrorClass::SessionInvalid | ErrorClass::Network | ErrorClass::Fatal => Err(e),
+            },
+        }
+    }
+
+    // 获取演出的所有场次/票档
+    pub async fn inspect(&self, item_id: &str) -> Result<ItemOverview> {
+        let ticket_info = self.get_ticket_info(item_id.to_string()).await?;
+        let item = &ticket_info.detail_view_component_map.item;
+
+        let mut skus = Vec::new();
+        for (i, base) in item.item.perform_bases.iter().enumerate() {
+            let session_index = session_index(i, base);
+            for perform in base.performs.iter() {
+                let perform_info = self
+                    .get_perform_info(item_id.to_string(), perform.perform_id.clone())
+                    .await?;
+                for (j, sku) in perform_info.perform.sku_list.iter().enumerate() {
+                    skus.push(SkuOverview {
+                        session_index,
+                        perform_id: perform.perform_id.clone(),
+                        perform_name: perform.perform_name.clone(),
+                        grade_index: j + 1,
+                        price_name: sku.price_name.clone(),
+                        price: sku.price.clone(),
+                        sku_id: sku.sku_id.clone(),
+                        sell_start_time: item.item.sell_start_time_str.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(ItemOverview {
+            item_id: item_id.to_string(),
+            item_name: item.static_data.item_base.item_name.clone(),
+            sell_start_time: item.item.sell_start_time_str.clone(),
+            skus,
+        })
+    }
+
+    // 解析配置中的候选场次/票档
+    pub async fn resolve_targets(
+        &self,
+        ticket_id: &str,
+        ticket_info: &TicketInfo,
+    ) -> Result<Vec<BuyTarget>> {
+        let bases = &ticket_info
+            .detail_view_component_map
+            .item
+            .item
+            .perform_bases;
+
+        let mut perform_infos: HashMap<String, PerformInfo> = HashMap::new();
+        let mut targets = Vec::new();
+
+        for candidate in self.account.ticket.candidates() {
+            let perform = candidate.sessions.resolve(bases)?;
+
+            if !perform_infos.contains_key(&perform.perform_id) {
+                let perform_info = self
+                    .get_perform_info(ticket_id.to_string(), perform.perform_id.clone())
+                    .await?;
+                perform_infos.insert(perform.perform_id.clone(), perform_info);
+            }
+
+            let sku = candidate
+                .grade
+                .resolve(&perform_infos[&perform.perform_id].perform.sku_list)?;
+
+            targets.push(BuyTarget {
+                perform_id: perform.perform_id.clone(),
+                perform_name: perform.perform_name.clone(),
+                item_id: sku.item_id.clone(),
+                sku_id: sku.sku_id.clone(),
+                sku_name: sku.price_name.clone(),
+                price: sku.price.clone(),
+            });
+        }
+        Ok(targets)
+    }
+
+    // 开抢前预热与大麦服务器的连接, 并统计握手耗时和往返延迟.
+    // 在stop_timestamp(发包时间)前结束, 不会推迟发包
+    pub async fn prewarm(&self, start_timestamp: i64, stop_timestamp: i64) {
+        wait_until(&self.clock, start_timestamp).await;
+
+        let mut elapsed = Vec::new();
+        loop {
+            let start = Instant::now();
+            if let Err(e) = self.client.transport.server_date().await {
+                warn!("预热连接失败, {}", e);
+                break;
+            }
+            let took = start.elapsed();
+            elapsed.push(took);
+            if elapsed.len() == PREWARM_RTT_PROBES + 1 {
+                log_prewarm(&elapsed);
+            }
+
+            // 首次请求后连续请求测量往返延迟, 之后每500ms请求一次保持连接活跃
+            let delay = match elapsed.len() <= PREWARM_RTT_PROBES {
+                true => 0,
+                false => 500,
+            };
+            if self.clock.now_millis() + delay + took.as_millis() as i64 >= stop_timestamp {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+        }
+
+        if elapsed.len() <= PREWARM_RTT_PROBES {
+            log_prewarm(&elapsed);
+        }
+    }
+
+    // 校准本地时钟与服务器时钟的偏差, 失败时不做校正
+    pub async fn sync_clock(&self) {
+        let start = Instant::now();
+        let sample = match &self.account.ntp_server {
+            Some(server) => sync_sntp(server).await,
+            None => sync_http(self.client.transport.as_ref(), 8).await,
+        };
+        match sample {
+            Ok(sample) => {
+                self.clock.set(sample);
+                info!(
+                    "校时完成, 本地时钟偏差:{}ms, 网络延迟:{}ms, 花费时间:{:?}",
+                    -sample.offset,
+                    sample.rtt,
                     start.elapsed()
                 );
-                Ok(false)
             }
+            Err(e) => warn!("校时失败, 使用本地时钟, {}", e),
+        }
+    }
+
+    // 开抢前重新校时, 启动时的校时结果可能已是数小时前. 距开抢不足两倍提前量时跳过
+    async fn resync_clock(&self, submit_timestamp: i64) {
+        let resync_at = submit_timestamp - CLOCK_RESYNC_BEFORE_MILLIS;
+        if resync_at - self.clock.now_millis() < CLOCK_RESYNC_BEFORE_MILLIS {
+            return;
         }
+        wait_until(&self.clock, resync_at).await;
+        self.sync_clock().await;
+        self.report.lock().unwrap().clock_offset = Some(self.clock.offset());
     }
 
-    pub fn ms_to_hms(&self, ms: i64) -> (u64, u64, f64) {
-        let sec = ms as f64 / 1000.0;
-        let hour = (sec / 3600.0) as u64;
-        let rem = sec % 3600.0;
-        let min = (rem / 60.0) as u64;
-        let sec = rem % 60.0;
-        (hour, min, sec)
+    // 记录一个步骤的耗时和结果
+    async fn step<T>(&self, name: &str, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        let started_at = Local::now().timestamp_millis();
+        let start = Instant::now();
+        let res = fut.await;
+        let error = res.as_ref().err().map(|e| e.to_string());
+        self.report
+            .lock()
+            .unwrap()
+            .steps
+            .push(StepRecord::new(name, started_at, start, error));
+        res
     }
 
+    // 运行抢购流程, 结束后保存运行报告. 抢购前出错时发送通知, 抢购失败已在buy中通知
     pub async fn run(&self) -> Result<()> {
+        let result = self.execute().await;
+
+        let mut notify_error = false;
+        if let Err(e) = &result {
+            let mut report = self.report.lock().unwrap();
+            if report.outcome == RunOutcome::Unfinished {
+                report.finish(RunOutcome::Error, Some(e.to_string()));
+                notify_error = true;
+            }
+        }
+        self.save_report();
+
+        match &result {
+            Err(e) if notify_error => {
+                self.notifiers
+                    .notify(Notification::from_error(&self.account.remark, e))
+                    .await
+            }
+            _ => {}
+        }
+
+        result
+    }
+
+    // 保存运行报告, 抢购结束后即保存一次, 付款监控结束后覆盖写入
+    fn save_report(&self) {
+        let report = {
+            let mut report = self.report.lock().unwrap();
+            report.finished_at = Some(Local::now().timestamp_millis());
+            report.requests.extend(self.client.take_records());
+            report.clone()
+        };
+
+        let dir = self
+            .account
+            .report_dir
+            .as_deref()
+            .unwrap_or(DEFAULT_REPORT_DIR);
+        match report.write(dir) {
+            Ok(path) => info!("运行报告已保存:{}", path.display()),
+            Err(e) => warn!("保存运行报告失败, {}", e),
+        }
+    }
+
+    async fn execute(&self) -> Result<()> {
         let ticket_id = self.account.ticket.id.clone();
-        let perfomr_idx = self.account.ticket.sessions - 1; // 场次索引
-        let sku_idx = self.account.ticket.grade - 1; // 票档索引
 
         info!("正在获取演唱会信息...");
-        let ticket_info = self.get_ticket_info(ticket_id.clone()).await?;
+        let ticket_info = self
+            .step("get_ticket_info", self.get_ticket_info(ticket_id.clone()))
+            .await?;
+
+        info!("正在获取场次/票档信息...");
+        let targets = self
+            .step(
+                "resolve_targets",
+                self.resolve_targets(&ticket_id, &ticket_info),
+            )
+            .await?;
 
         let ticket_name = ticket_info
             .detail_view_component_map
@@ -257,30 +929,6 @@ impl DmTicket {
             .item_base
             .item_name;
 
-        let perform_id = ticket_info
-            .detail_view_component_map
-            .item
-            .item
-            .perform_bases[perfomr_idx]
-            .performs[0]
-            .perform_id
-            .clone();
-
-        let perform_name = ticket_info
-            .detail_view_component_map
-            .item
-            .item
-            .perform_bases[perfomr_idx]
-            .performs[0]
-            .perform_name
-            .clone();
-
-        info!("正在获取场次/票档信息...");
-        let perform_info = self.get_perform_info(ticket_id, perform_id).await?;
-        let sku_id = perform_info.perform.sku_list[sku_idx].sku_id.clone();
-        let sku_name = perform_info.perform.sku_list[sku_idx].price_name.clone();
-        let item_id = perform_info.perform.sku_list[sku_idx].item_id.clone();
-
         let start_time_str = ticket_info
             .detail_view_component_map
             .item
@@ -295,47 +943,376 @@ impl DmTicket {
 
         println!(
             "\r\n\t账号备注:{}\n\t门票名称:{}\n\t场次名称:{}\n\t票档名称:{}\n\t开抢时间:{}\n",
-            self.account.remark, ticket_name, perform_name, sku_name, start_time_str
+            self.account.remark,
+            ticket_name,
+            targets[0].perform_name,
+            targets[0].sku_name,
+            start_time_str
         );
+        for (i, target) in targets.iter().enumerate().skip(1) {
+            println!("\t备选{}:{}/{}", i, target.perform_name, target.sku_name);
+        }
 
-        let (s, r) = async_channel::unbounded::<bool>();
+        {
+            let mut report = self.report.lock().unwrap();
+            report.item_name = Some(ticket_name.clone());
+            report.targets = targets.clone();
+            report.sell_start_time = Some(start_time_str.clone());
+            report.sell_start_timestamp = Some(start_timestamp);
+        }
+
+        if self.account.dry_run.unwrap_or(false) {
+            self.step("dry_run", self.dry_run(&targets[0])).await?;
+            self.report.lock().unwrap().finish(RunOutcome::DryRun, None);
+            return Ok(());
+        }
+
+        self.step("preflight", self.preflight(&targets[0])).await?;
+
+        self.step("sync_clock", async {
+            self.sync_clock().await;
+            Ok(())
+        })
+        .await?;
+        self.report.lock().unwrap().clock_offset = Some(self.clock.offset());
 
-        let interval = self.account.interval.unwrap_or(50);
+        let interval = self.account.interval.unwrap_or(200);
         let earliest_submit_time = self.account.earliest_submit_time.unwrap_or(1);
+        let prewarm_time = self.account.prewarm_time.unwrap_or(3000);
+        let submit_timestamp = start_timestamp - earliest_submit_time;
 
-        loop {
-            tokio::select! {
-                _ = signal::ctrl_c() => {
-                    info!("CTRL-C, 退出程序...");
-                    return Ok(());
-                }
+        tokio::select! {
+            _ = signal::ctrl_c() => {
+                info!("CTRL-C, 退出程序...");
+                self.report
+                    .lock()
+                    .unwrap()
+                    .finish(RunOutcome::Cancelled, None);
+                return Ok(());
+            }
 
-                _ = tokio::time::sleep(Duration::from_millis(interval)) => {
-                    let local: DateTime<Local> = Local::now();
-                    let millis = local.timestamp_millis();
-                    let time_left_millis = start_timestamp - millis;
-                    if time_left_millis <= earliest_submit_time {
-                        let _ = s.send(true).await;
-                    }else{
-                        let (hours, minutes, seconds) = self.ms_to_hms(time_left_millis);
-                        print!("\r\t开抢倒计时:{}小时:{}分钟:{:.3}秒\t", hours, minutes, seconds);
-                        let _ =io::stdout().flush();
-                    }
+            _ = countdown(&self.clock, start_timestamp, interval) => {}
+
+            _ = wait_until(&self.clock, submit_timestamp) => {}
 
-                    // info!("剩余时间毫秒:{}", time_left_millis);
+            // 重新校时后等待发包, 不会结束select
+            _ = async {
+                self.resync_clock(submit_timestamp).await;
+                future::pending::<()>().await
+            } => {}
+
+            // 预热结束后等待发包, 不会结束select
+            _ = async {
+                if prewarm_time > 0 {
+                    self.prewarm(start_timestamp - prewarm_time, submit_timestamp).await;
                 }
+                future::pending::<()>().await
+            } => {}
+        }
+        println!();
 
-                _ = r.recv() => {
-                    for _ in 0..2 {
-                        if let Ok(res) = self.buy(&item_id, &sku_id).await {
-                            if res {// 抢购成功, 退出
-                                return Ok(());
-                            }
-                        }
+        if let Some(res) = self.step("buy", self.buy(&targets)).await? {
+            let order_id = parse_order_id(&res.data);
+            {
+                let mut report = self.report.lock().unwrap();
+                report.finish(RunOutcome::Success, None);
+                report.order_id = order_id.clone();
+            }
+            // 付款监控可能持续较长时间, 先保存报告, 避免进程被终止时丢失
+            self.save_report();
+
+            match order_id {
+                Some(order_id) => {
+                    self.notify_success(&order_id).await;
+                    if let Some(watch) = &self.account.pay_watch {
+                        self.watch_order(&order_id, watch).await;
                     }
-                    return Ok(());
+                }
+                None => {
+                    warn!("未能解析订单号, 请前往手机APP查看订单.");
+                    self.notify_success("").await;
                 }
             }
         }
+        Ok(())
+    }
+}
+
+// 开抢前多少毫秒重新校时
+const CLOCK_RESYNC_BEFORE_MILLIS: i64 = 30_000;
+
+// 预热时测量往返延迟的请求次数, 不含首次建立连接的请求
+const PREWARM_RTT_PROBES: usize = 3;
+
+// 打印预热结果, 首次请求包含握手耗时, 之后请求的中位数作为往返延迟
+fn log_prewarm(elapsed: &[Duration]) {
+    let (first, rest) = match elapsed.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let mut rest = rest.to_vec();
+    rest.sort_unstable();
+    match rest.get(rest.len() / 2) {
+        Some(rtt) => info!(
+            "连接预热完成, 首次请求:{:?}, 握手耗时约:{:?}, 往返延迟:{:?}",
+            first,
+            first.saturating_sub(*rtt),
+            rtt
+        ),
+        None => info!(
+            "连接预热完成, 首次请求:{:?}, 预热时间过短, 未能测量往返延迟",
+            first
+        ),
+    }
+}
+
+// 第i个之后是否还有未售罄的候选
+fn has_next(targets: &[BuyTarget], i: usize, sold_out: &HashSet<String>) -> bool {
+    targets[i + 1..]
+        .iter()
+        .any(|t| !sold_out.contains(&t.sku_id))
+}
+
+// 是否为售罄类错误
+fn is_sold_out(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<DmError>()
+        .map(|e| e.is_sold_out())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{
+        capture::load_captures,
+        transport::fake::{failure, success, FakeTransport},
+    };
+
+    const DRY_RUN_CAPTURE: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/dry_run.jsonl");
+
+    const ACCOUNT: &str = r#"
+cookie: "cna=abc"
+remark: 抢购
+ticket:
+  id: "710947802955"
+  num: 1
+  sessions: 1
+  grade: 1
+"#;
+
+    const SOLD_OUT: &str = "B-00203-200-001::库存不足";
+    const THROTTLED: &str = "RGV587_ERROR::SM::哎哟喂,被挤爆啦,请稍后重试!";
+
+    // 抓包记录中生成订单的响应
+    fn build_response() -> DmRes {
+        load_captures(DRY_RUN_CAPTURE)
+            .unwrap()
+            .into_iter()
+            .find(|c| c.api == "mtop.trade.order.build.h5")
+            .unwrap()
+            .response()
+            .unwrap()
+    }
+
+    fn target(sku_id: &str) -> BuyTarget {
+        BuyTarget {
+            perform_id: "200000".to_string(),
+            perform_name: "场次".to_string(),
+            item_id: "710947802955".to_string(),
+            sku_id: sku_id.to_string(),
+            sku_name: format!("票档{}", sku_id),
+            price: "680".to_string(),
+        }
+    }
+
+    // 生成订单时返回build(skuId)的结果, 提交订单时按顺序返回submits中的ret
+    async fn dm_ticket(
+        build: impl Fn(&str) -> Option<&'static str> + Send + Sync + 'static,
+        submits: Vec<&'static str>,
+    ) -> (DmTicket, Arc<FakeTransport>) {
+        let build_res = build_response();
+        let submits = Mutex::new(submits.into_iter());
+        let transport = Arc::new(FakeTransport::new(move |api, data| match api {
+            "mtop.trade.order.build.h5" => {
+                let buy_param = data["buyParam"].as_str().unwrap_or_default();
+                let sku_id = buy_param.rsplit('_').next().unwrap_or_default();
+                Ok(match build(sku_id) {
+                    Some(ret) => failure(ret),
+                    None => build_res.clone(),
+                })
+            }
+            "mtop.trade.order.create.h5" => match submits.lock().unwrap().next() {
+                Some(crate::error::SUCCESS_FLAG) | None => {
+                    Ok(success(json!({"bizOrderId": "9000000"})))
+                }
+                Some("network") => Err(reqwest::Client::new()
+                    .get("网络错误")
+                    .build()
+                    .unwrap_err()
+                    .into()),
+                Some(ret) => Ok(failure(ret)),
+            },
+            _ => Ok(failure("FAIL_SYS_API_NOT_FOUNDED::请求API不存在")),
+        }));
+
+        let account: Account = serde_yaml::from_str(ACCOUNT).unwrap();
+        let client = DmClient::with_transport(account.cookie.clone(), transport.clone())
+            .await
+            .unwrap();
+        (DmTicket::with_client(account, client), transport)
+    }
+
+    // 已发送请求的简写: build:skuId或create
+    fn calls(transport: &FakeTransport) -> Vec<String> {
+        transport
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|r| match r.params["api"].as_str().unwrap_or_default() {
+                "mtop.trade.order.build.h5" => {
+                    let data: Value =
+                        serde_json::from_str(r.form["data"].as_str().unwrap()).unwrap();
+                    let buy_param = data["buyParam"].as_str().unwrap().to_string();
+                    format!("build:{}", buy_param.rsplit('_').next().unwrap())
+                }
+                "mtop.trade.order.create.h5" => "create".to_string(),
+                api => api.to_string(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn next_target_when_sold_out_at_build() {
+        let (dm_ticket, transport) =
+            dm_ticket(|sku| (sku == "A").then_some(SOLD_OUT), vec![]).await;
+        let targets = [target("A"), target("B")];
+        let mut sold_out = HashSet::new();
+
+        dm_ticket.attempt(&targets, &mut sold_out).await.unwrap();
+        assert_eq!(calls(&transport), ["build:A", "build:B", "create"]);
+        assert_eq!(sold_out, HashSet::from(["A".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn next_target_when_sold_out_at_submit() {
+        let (dm_ticket, transport) = dm_ticket(|_| None, vec![SOLD_OUT]).await;
+        let targets = [target("A"), target("B")];
+        let mut sold_out = HashSet::new();
+
+        dm_ticket.attempt(&targets, &mut sold_out).await.unwrap();
+        assert_eq!(
+            calls(&transport),
+            ["build:A", "create", "build:B", "create"]
+        );
+    }
+
+    #[tokio::test]
+    async fn retry_all_when_all_sold_out() {
+        let (dm_ticket, transport) = dm_ticket(|_| Some(SOLD_OUT), vec![]).await;
+        let targets = [target("A"), target("B")];
+        let mut sold_out = HashSet::new();
+
+        let e = dm_ticket
+            .attempt(&targets, &mut sold_out)
+            .await
+            .unwrap_err();
+        assert!(is_sold_out(&e), "{}", e);
+        assert_eq!(sold_out.len(), 2);
+
+        dm_ticket
+            .attempt(&targets, &mut sold_out)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            calls(&transport),
+            ["build:A", "build:B", "build:A", "build:B"]
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_sold_out_on_retry() {
+        // A售罄, B第一次生成订单时被限流
+        let b_builds = AtomicUsize::new(0);
+        let (dm_ticket, transport) = dm_ticket(
+            move |sku| match sku {
+                "A" => Some(SOLD_OUT),
+                _ if b_builds.fetch_add(1, Ordering::SeqCst) == 0 => Some(THROTTLED),
+                _ => None,
+            },
+            vec![],
+        )
+        .await;
+        let targets = [target("A"), target("B")];
+        let mut sold_out = HashSet::new();
+
+        let e = dm_ticket
+            .attempt(&targets, &mut sold_out)
+            .await
+            .unwrap_err();
+        assert_eq!(ErrorClass::of(&e), ErrorClass::Throttled);
+
+        dm_ticket.attempt(&targets, &mut sold_out).await.unwrap();
+        assert_eq!(
+            calls(&transport),
+            ["build:A", "build:B", "build:B", "create"]
+        );
+    }
+
+    #[tokio::test]
+    async fn reuse_order_after_retryable_submit() {
+        for failure in [THROTTLED, "network"] {
+            let (dm_ticket, transport) = dm_ticket(|_| None, vec![failure]).await;
+            let targets = [target("A"), target("B")];
+            let mut sold_out = HashSet::new();
+
+            dm_ticket
+                .attempt(&targets, &mut sold_out)
+                .await
+                .unwrap_err();
+            dm_ticket.attempt(&targets, &mut sold_out).await.unwrap();
+            assert_eq!(
+                calls(&transport),
+                ["build:A", "create", "create"],
+                "{}",
+                failure
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn drop_order_after_business_error() {
+        let (dm_ticket, transport) =
+            dm_ticket(|_| None, vec!["B-00203-200-100::网络开小差了"]).await;
+        let targets = [target("A")];
+        let mut sold_out = HashSet::new();
+
+        dm_ticket
+            .attempt(&targets, &mut sold_out)
+            .await
+            .unwrap_err();
+        assert!(dm_ticket.pending_order.lock().unwrap().is_none());
+        dm_ticket.attempt(&targets, &mut sold_out).await.unwrap();
+        assert_eq!(
+            calls(&transport),
+            ["build:A", "create", "build:A", "create"]
+        );
+    }
+
+    #[tokio::test]
+    async fn drop_order_not_in_targets() {
+        let (dm_ticket, transport) = dm_ticket(|_| None, vec![]).await;
+        let order_info = serde_json::from_value(build_response().data).unwrap();
+        *dm_ticket.pending_order.lock().unwrap() = Some(("X".to_string(), order_info));
+        let mut sold_out = HashSet::new();
+
+        dm_ticket
+            .attempt(&[target("A")], &mut sold_out)
+            .await
+            .unwrap();
+        assert_eq!(calls(&transport), ["build:A", "create"]);
     }
 }
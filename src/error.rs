@@ -0,0 +1,189 @@
+use std::fmt;
+
+// 调用成功标识
+pub const SUCCESS_FLAG: &str = "SUCCESS::调用成功";
+
+// 售罄/库存不足相关提示
+const SOLD_OUT_KEYWORDS: [&str; 5] = ["售罄", "库存不足", "没有库存", "已售完", "无票"];
+
+/// 大麦API `ret` 字段解析出的错误类型.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DmError {
+    /// 请求被限流, 如: `RGV587_ERROR::SM::哎哟喂,被挤爆啦,请稍后重试!`
+    Throttled(String),
+
+    /// `_m_h5_tk`令牌过期或为空, 如: `FAIL_SYS_TOKEN_EXOIRED::令牌过期`
+    TokenExpired(String),
+
+    /// 登录态失效, cookie需要重新获取, 如: `FAIL_SYS_SESSION_EXPIRED::Session过期`
+    SessionInvalid(String),
+
+    /// 门票已售罄/库存不足.
+    SoldOut(String),
+
+    /// 其他业务错误, 如: `B-00203-200-100::网络开小差了，再试一次吧~`
+    Business { code: String, msg: String },
+}
+
+impl DmError {
+    /// 解析单条`ret`, 调用成功时返回None.
+    pub fn parse(ret: &str) -> Option<Self> {
+        if ret.starts_with("SUCCESS") {
+            return None;
+        }
+
+        let (code, msg) = match ret.split_once("::") {
+            Some((code, msg)) => (code.to_string(), msg.to_string()),
+            None => (ret.to_string(), String::new()),
+        };
+
+        let err = if code.starts_with("RGV587_ERROR") || code == "FAIL_SYS_USER_VALIDATE" {
+            Self::Throttled(msg)
+        } else if code.starts_with("FAIL_SYS_TOKEN_") {
+            Self::TokenExpired(msg)
+        } else if code.starts_with("FAIL_SYS_SESSION_") || code == "FAIL_SYS_LOGIN_REQUIRED" {
+            Self::SessionInvalid(msg)
+        } else if SOLD_OUT_KEYWORDS.iter().any(|k| msg.contains(k)) {
+            Self::SoldOut(msg)
+        } else {
+            Self::Business { code, msg }
+        };
+        Some(err)
+    }
+
+    /// 解析`DmRes.ret`, 包含成功标识时返回None.
+    pub fn from_ret(ret: &[String]) -> Option<Self> {
+        if ret.iter().any(|r| r == SUCCESS_FLAG) {
+            return None;
+        }
+        match ret.iter().find_map(|r| Self::parse(r)) {
+            Some(err) => Some(err),
+            None if ret.is_empty() => Some(Self::Business {
+                code: "EMPTY_RET".to_string(),
+                msg: String::new(),
+            }),
+            None => None,
+        }
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        matches!(self, Self::Throttled(_))
+    }
+
+    pub fn is_token_expired(&self) -> bool {
+        matches!(self, Self::TokenExpired(_))
+    }
+
+    pub fn is_session_invalid(&self) -> bool {
+        matches!(self, Self::SessionInvalid(_))
+    }
+
+    pub fn is_sold_out(&self) -> bool {
+        matches!(self, Self::SoldOut(_))
+    }
+}
+
+impl fmt::Display for DmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Throttled(msg) => write!(f, "请求被限流: {}", msg),
+            Self::TokenExpired(msg) => write!(f, "令牌过期: {}", msg),
+            Self::SessionInvalid(msg) => write!(f, "登录失效, 请重新获取cookie: {}", msg),
+            Self::SoldOut(msg) => write!(f, "门票已售罄: {}", msg),
+            Self::Business { code, msg } => write!(f, "{}::{}", code, msg),
+        }
+    }
+}
+
+impl std::error::Error for DmError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ret(list: &[&str]) -> Vec<String> {
+        list.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_ret() {
+        let cases = [
+            (
+                "RGV587_ERROR::SM::哎哟喂,被挤爆啦,请稍后重试!",
+                Some(DmError::Throttled(
+                    "SM::哎哟喂,被挤爆啦,请稍后重试!".to_string(),
+                )),
+            ),
+            (
+                "FAIL_SYS_USER_VALIDATE::哎哟喂,被挤爆啦,请稍后重试",
+                Some(DmError::Throttled("哎哟喂,被挤爆啦,请稍后重试".to_string())),
+            ),
+            (
+                "FAIL_SYS_TOKEN_EXOIRED::令牌过期",
+                Some(DmError::TokenExpired("令牌过期".to_string())),
+            ),
+            (
+                "FAIL_SYS_TOKEN_EMPTY::令牌为空",
+                Some(DmError::TokenExpired("令牌为空".to_string())),
+            ),
+            (
+                "FAIL_SYS_SESSION_EXPIRED::Session过期",
+                Some(DmError::SessionInvalid("Session过期".to_string())),
+            ),
+            (
+                "B-00203-200-001::库存不足",
+                Some(DmError::SoldOut("库存不足".to_string())),
+            ),
+            (
+                "B-00203-200-100::网络开小差了，再试一次吧~",
+                Some(DmError::Business {
+                    code: "B-00203-200-100".to_string(),
+                    msg: "网络开小差了，再试一次吧~".to_string(),
+                }),
+            ),
+            (
+                "UNKNOWN_ERROR",
+                Some(DmError::Business {
+                    code: "UNKNOWN_ERROR".to_string(),
+                    msg: String::new(),
+                }),
+            ),
+            (SUCCESS_FLAG, None),
+            ("SUCCESS::接口调用成功", None),
+        ];
+
+        for (ret, expected) in cases {
+            assert_eq!(DmError::parse(ret), expected, "ret: {}", ret);
+        }
+    }
+
+    #[test]
+    fn from_ret() {
+        let cases = [
+            (ret(&[SUCCESS_FLAG]), None),
+            (
+                ret(&["FAIL_SYS_TOKEN_EXOIRED::令牌过期", SUCCESS_FLAG]),
+                None,
+            ),
+            (
+                ret(&[]),
+                Some(DmError::Business {
+                    code: "EMPTY_RET".to_string(),
+                    msg: String::new(),
+                }),
+            ),
+            (
+                ret(&["SUCCESS::调用成功了", "B-00203-200-001::库存不足"]),
+                Some(DmError::SoldOut("库存不足".to_string())),
+            ),
+            (
+                ret(&["FAIL_SYS_SESSION_EXPIRED::Session过期", "RGV587_ERROR::SM"]),
+                Some(DmError::SessionInvalid("Session过期".to_string())),
+            ),
+        ];
+
+        for (ret, expected) in cases {
+            assert_eq!(DmError::from_ret(&ret), expected, "ret: {:?}", ret);
+        }
+    }
+}
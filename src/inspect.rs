@@ -0,0 +1,193 @@
+use anyhow::Result;
+use serde::Serialize;
+
+// 演出的一个场次/票档
+#[derive(Serialize, Debug, Clone)]
+pub struct SkuOverview {
+    // 包含多个场次的场次组无法通过序号选择, 为None, 需通过performId选择
+    pub session_index: Option<usize>,
+    pub perform_id: String,
+    pub perform_name: String,
+    pub grade_index: usize,
+    pub price_name: String,
+    pub price: String,
+    pub sku_id: String,
+    pub sell_start_time: String,
+}
+
+// 演出的场次/票档概览, 用于填写配置中的sessions/grade
+#[derive(Serialize, Debug, Clone)]
+pub struct ItemOverview {
+    pub item_id: String,
+    pub item_name: String,
+    pub sell_start_time: String,
+    pub skus: Vec<SkuOverview>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+const HEADERS: [&str; 8] = [
+    "场次序号",
+    "performId",
+    "场次名称",
+    "票档序号",
+    "票档名称",
+    "价格",
+    "skuId",
+    "开抢时间",
+];
+
+const INDEX_NOTE: &str = "场次序号为-的场次无法通过序号选择, 请在sessions中填写performId";
+
+// 终端显示宽度, 中文等非ASCII字符按2计算
+fn display_width(s: &str) -> usize {
+    s.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum()
+}
+
+fn pad(s: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        s,
+        " ".repeat(width.saturating_sub(display_width(s)))
+    )
+}
+
+fn csv_field(s: &str) -> String {
+    match s.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    }
+}
+
+impl SkuOverview {
+    fn columns(&self) -> [String; 8] {
+        [
+            self.session_index
+                .map(|i| i.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            self.perform_id.clone(),
+            self.perform_name.clone(),
+            self.grade_index.to_string(),
+            self.price_name.clone(),
+            self.price.clone(),
+            self.sku_id.clone(),
+            self.sell_start_time.clone(),
+        ]
+    }
+}
+
+impl ItemOverview {
+    pub fn render(&self, format: OutputFormat) -> Result<String> {
+        Ok(match format {
+            OutputFormat::Table => self.table(),
+            OutputFormat::Json => serde_json::to_string_pretty(self)?,
+            OutputFormat::Csv => self.csv(),
+        })
+    }
+
+    fn table(&self) -> String {
+        let rows = self.skus.iter().map(|s| s.columns()).collect::<Vec<_>>();
+
+        let mut widths = HEADERS.map(display_width);
+        for row in rows.iter() {
+            for (width, column) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(display_width(column));
+            }
+        }
+
+        let line = |columns: &[String]| {
+            columns
+                .iter()
+                .zip(widths.iter())
+                .map(|(c, w)| pad(c, *w))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        };
+
+        let mut lines = vec![
+            format!("门票ID:{}", self.item_id),
+            format!("门票名称:{}", self.item_name),
+            format!("开抢时间:{}", self.sell_start_time),
+            String::new(),
+            line(&HEADERS.map(String::from)),
+            widths
+                .iter()
+                .map(|w| "-".repeat(*w))
+                .collect::<Vec<_>>()
+                .join("  "),
+        ];
+        lines.extend(rows.iter().map(|row| line(row)));
+        if self.skus.iter().any(|s| s.session_index.is_none()) {
+            lines.push(String::new());
+            lines.push(INDEX_NOTE.to_string());
+        }
+        lines.join("\n")
+    }
+
+    fn csv(&self) -> String {
+        let mut lines = vec![HEADERS.join(",")];
+        lines.extend(self.skus.iter().map(|sku| {
+            sku.columns()
+                .iter()
+                .map(|c| csv_field(c))
+                .collect::<Vec<_>>()
+                .join(",")
+        }));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overview() -> ItemOverview {
+        let sku = |session_index, perform_id: &str, perform_name: &str| SkuOverview {
+            session_index,
+            perform_id: perform_id.to_string(),
+            perform_name: perform_name.to_string(),
+            grade_index: 1,
+            price_name: "看台380元".to_string(),
+            price: "380".to_string(),
+            sku_id: "500000".to_string(),
+            sell_start_time: "2026-10-18 20:00:00".to_string(),
+        };
+        ItemOverview {
+            item_id: "710947802955".to_string(),
+            item_name: "模拟演唱会".to_string(),
+            sell_start_time: "2026-10-18 20:00:00".to_string(),
+            skus: vec![
+                sku(Some(1), "200000", "2026-11-01 周六 19:30"),
+                sku(None, "200001", "2026-11-01 周六 21:30"),
+            ],
+        }
+    }
+
+    #[test]
+    fn render_unindexed_session() {
+        let overview = overview();
+
+        let table = overview.render(OutputFormat::Table).unwrap();
+        assert!(table.contains(INDEX_NOTE));
+        assert!(table
+            .lines()
+            .any(|line| line.starts_with("-") && line.contains("200001")));
+
+        let csv = overview.render(OutputFormat::Csv).unwrap();
+        assert_eq!(
+            csv.lines().nth(2),
+            Some("-,200001,2026-11-01 周六 21:30,1,看台380元,380,500000,2026-10-18 20:00:00")
+        );
+
+        let json = overview.render(OutputFormat::Json).unwrap();
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert!(value["skus"][1]["session_index"].is_null());
+    }
+}
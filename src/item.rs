@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use reqwest::Url;
+
+// 门票详情页所在的域名
+const ITEM_HOSTS: [&str; 4] = ["m.damai.cn", "detail.damai.cn", "www.damai.cn", "damai.cn"];
+
+// 需要请求后才能得到门票详情页的短链接域名
+const SHORT_LINK_HOSTS: [&str; 3] = ["m.tb.cn", "tb.cn", "t.damai.cn"];
+
+fn is_item_id(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+// 门票详情页URL中的itemId, detail.damai.cn使用id参数
+fn item_id_from_url(url: &Url) -> Option<String> {
+    if !ITEM_HOSTS.contains(&url.host_str()?) {
+        return None;
+    }
+    url.query_pairs()
+        .find(|(k, v)| (k == "itemId" || k == "id") && is_item_id(v))
+        .map(|(_, v)| v.to_string())
+}
+
+// 短链接跳转到登录页时, 门票详情页链接在redirectURL参数中
+fn item_id_from_redirect(url: &Url) -> Option<String> {
+    item_id_from_url(url).or_else(|| {
+        url.query_pairs()
+            .filter(|(k, _)| k == "redirectURL" || k == "redirect_url")
+            .find_map(|(_, v)| Url::parse(&v).ok().and_then(|u| item_id_from_url(&u)))
+    })
+}
+
+// 页面内容中location/href指向的链接, 如location.href="..."、location.replace('...')、<a href="...">
+fn redirect_targets(text: &str) -> impl Iterator<Item = &str> {
+    ["location.href", "location.replace", "location", "href"]
+        .into_iter()
+        .flat_map(move |key| {
+            text.match_indices(key)
+                .map(move |(i, _)| &text[i + key.len()..])
+        })
+        .filter_map(|rest| {
+            let rest = rest.trim_start().strip_prefix(['=', '('])?.trim_start();
+            let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+            let rest = &rest[1..];
+            rest.find(quote).map(|end| &rest[..end])
+        })
+}
+
+// 在页面内容中查找跳转目标为门票详情页的itemId, 用于通过js跳转的短链接
+fn item_id_from_text(text: &str) -> Option<String> {
+    redirect_targets(text).find_map(|target| {
+        let target = target.replace("&amp;", "&");
+        let target = match target.strip_prefix("//") {
+            Some(rest) => format!("https://{}", rest),
+            None => target,
+        };
+        Url::parse(&target)
+            .ok()
+            .and_then(|url| item_id_from_redirect(&url))
+    })
+}
+
+/// 解析门票ID, 支持纯数字ID、m.damai.cn/detail.damai.cn门票详情页URL及短链接.
+///
+/// 短链接会请求一次, 从跳转后的URL或页面中location/href指向的门票详情页链接查找itemId.
+pub async fn resolve_item_id(input: &str) -> Result<String> {
+    let input = input.trim();
+    if is_item_id(input) {
+        return Ok(input.to_string());
+    }
+
+    let url = Url::parse(input).map_err(|_| anyhow!("无法识别的门票ID或链接:{}", input))?;
+    if let Some(id) = item_id_from_url(&url) {
+        return Ok(id);
+    }
+
+    let host = url.host_str().unwrap_or_default();
+    if !SHORT_LINK_HOSTS.contains(&host) {
+        return Err(anyhow!(
+            "无法识别的门票链接:{}, 请使用大麦门票详情页链接或itemId",
+            input
+        ));
+    }
+
+    let response = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .user_agent("Mozilla/5.0 (iPhone; CPU iPhone OS 13_2_3 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/13.0.3")
+        .use_rustls_tls()
+        .build()?
+        .get(url)
+        .send()
+        .await?;
+
+    if let Some(id) = item_id_from_redirect(response.url()) {
+        return Ok(id);
+    }
+    item_id_from_text(&response.text().await?)
+        .ok_or_else(|| anyhow!("短链接:{}未跳转到大麦门票详情页", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_item_url() {
+        let cases = [
+            (
+                "https://m.damai.cn/damai/detail/item.html?itemId=710947802955&spm=a2o71.home",
+                Some("710947802955"),
+            ),
+            (
+                "https://detail.damai.cn/item.htm?spm=a2oeg.home&id=710947802955",
+                Some("710947802955"),
+            ),
+            (
+                "https://damai.cn/item.htm?itemId=710947802955",
+                Some("710947802955"),
+            ),
+            ("https://m.damai.cn/damai/detail/item.html?itemId=abc", None),
+            ("https://m.damai.cn/damai/detail/item.html", None),
+            ("https://example.com/item.htm?itemId=710947802955", None),
+        ];
+
+        for (url, expected) in cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(item_id_from_url(&url).as_deref(), expected, "url: {}", url);
+        }
+    }
+
+    #[test]
+    fn parse_redirect_url() {
+        let cases = [
+            (
+                "https://login.m.taobao.com/login.htm?redirectURL=https%3A%2F%2Fm.damai.cn%2Fitem.html%3FitemId%3D710947802955",
+                Some("710947802955"),
+            ),
+            (
+                "https://login.m.taobao.com/login.htm?redirectURL=https%3A%2F%2Fexample.com%2F%3FitemId%3D710947802955",
+                None,
+            ),
+            ("https://login.m.taobao.com/login.htm?itemId=710947802955", None),
+        ];
+
+        for (url, expected) in cases {
+            let url = Url::parse(url).unwrap();
+            assert_eq!(
+                item_id_from_redirect(&url).as_deref(),
+                expected,
+                "url: {}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn parse_item_text() {
+        let cases = [
+            (
+                r#"<script>location.href="https://m.damai.cn/damai/detail/item.html?itemId=710947802955"</script>"#,
+                Some("710947802955"),
+            ),
+            (
+                r#"<script>window.location.replace('//m.damai.cn/damai/detail/item.html?itemId=710947802955')</script>"#,
+                Some("710947802955"),
+            ),
+            (
+                r#"<a href="https://detail.damai.cn/item.htm?spm=a2oeg&amp;id=710947802955">查看</a>"#,
+                Some("710947802955"),
+            ),
+            // 页面中其他位置的itemId不是跳转目标
+            (
+                r#"<div data-url="https://m.damai.cn/item.html?itemId=1"></div><script>location.href = "https://m.damai.cn/item.html?itemId=710947802955";</script>"#,
+                Some("710947802955"),
+            ),
+            (r#"<a href="https://example.com/item.htm?itemId=1">"#, None),
+            ("推荐演出 itemId=710947802955", None),
+            ("itemId=&id=1", None),
+            ("<html>页面不存在</html>", None),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(
+                item_id_from_text(text).as_deref(),
+                expected,
+                "text: {}",
+                text
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_without_request() {
+        let cases = [
+            ("710947802955", Some("710947802955")),
+            (" 710947802955\n", Some("710947802955")),
+            (
+                "https://m.damai.cn/damai/detail/item.html?itemId=710947802955",
+                Some("710947802955"),
+            ),
+            ("https://example.com/item.htm?itemId=710947802955", None),
+            ("七月演唱会", None),
+            ("", None),
+        ];
+
+        for (input, expected) in cases {
+            let id = resolve_item_id(input).await.ok();
+            assert_eq!(id.as_deref(), expected, "input: {:?}", input);
+        }
+    }
+}
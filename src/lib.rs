@@ -1,4 +1,18 @@
+pub mod capture;
 pub mod client;
+pub mod clock;
 pub mod config;
 pub mod dm;
+pub mod error;
+pub mod inspect;
+pub mod item;
+pub mod matcher;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod models;
+pub mod notify;
+pub mod payment;
+pub mod report;
+pub mod retry;
+pub mod scheduler;
+pub mod transport;
@@ -1,11 +1,165 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand};
 use dm_ticket::{
-    config::{load_global_config, Config},
+    config::{self, load_config, Account, Config, DEFAULT_CONFIG_PATH},
     dm,
+    inspect::OutputFormat,
+    item::resolve_item_id,
+    notify::{Notification, Notifiers},
 };
 use futures::future::join_all;
+use log::{error, info};
 use dotenv::dotenv;
-use std::env;
+use std::{env, process, sync::Arc};
+
+#[derive(Parser, Debug)]
+#[command(name = "dm-ticket", version, about = "大麦网抢票")]
+struct Cli {
+    /// 配置文件路径
+    #[arg(short, long, global = true, default_value = DEFAULT_CONFIG_PATH)]
+    config: String,
+
+    /// 只处理指定备注的账号, 可多次指定
+    #[arg(short, long, global = true)]
+    account: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// 抢票, 未指定子命令时默认执行
+    Run,
+    /// 试运行, 只生成订单并打印将要提交的数据, 不提交订单
+    DryRun,
+    /// 查看演出的场次和票档
+    Inspect {
+        /// 门票ID, 或门票详情页URL、短链接
+        item_id: String,
+
+        /// 以JSON格式输出
+        #[arg(long, conflicts_with = "csv")]
+        json: bool,
+
+        /// 以CSV格式输出
+        #[arg(long)]
+        csv: bool,
+    },
+    /// 检查账号cookie是否有效
+    CheckLogin,
+    /// 校验配置文件
+    ValidateConfig,
+    /// 配置文件工具
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// 输出配置文件的JSON Schema
+    Schema {
+        /// 输出文件路径, 不指定时输出到标准输出
+        path: Option<String>,
+    },
+}
+
+// 按备注选择账号, 未指定时选择所有账号
+fn select_accounts(config: &Config, remarks: &[String]) -> Result<Vec<Account>> {
+    if remarks.is_empty() {
+        return Ok(config.accounts.clone());
+    }
+    for remark in remarks.iter() {
+        if !config.accounts.iter().any(|a| &a.remark == remark) {
+            return Err(anyhow!(
+                "未找到账号:{}, 可选账号:{:?}",
+                remark,
+                config.accounts.iter().map(|a| &a.remark).collect::<Vec<_>>()
+            ));
+        }
+    }
+    Ok(config
+        .accounts
+        .iter()
+        .filter(|a| remarks.contains(&a.remark))
+        .cloned()
+        .collect())
+}
+
+async fn run(accounts: Vec<Account>, dry_run: bool) {
+    let mut handlers = Vec::new();
+
+    for mut account in accounts {
+        if dry_run {
+            account.dry_run = Some(true);
+        }
+        let handler = tokio::spawn(async move {
+            let remark = account.remark.clone();
+            let configs = account.notifiers.clone().unwrap_or_default();
+            let notifiers = match Notifiers::new(&configs) {
+                Ok(notifiers) => Arc::new(notifiers),
+                Err(e) => {
+                    error!("账号:{}, 通知配置错误, {}", remark, e);
+                    return;
+                }
+            };
+            let dm_ticket = match dm::DmTicket::new(account).await {
+                Ok(dm_ticket) => dm_ticket.with_notifiers(notifiers.clone()),
+                Err(e) => {
+                    error!("账号:{}, 初始化失败, {}", remark, e);
+                    notifiers.notify(Notification::from_error(&remark, &e)).await;
+                    return;
+                }
+            };
+            // 运行中的错误由run发送通知
+            if let Err(e) = dm_ticket.run().await {
+                error!("账号:{}, {}", remark, e);
+            }
+        });
+        handlers.push(handler);
+    }
+    join_all(handlers).await;
+}
+
+// 检查所有账号的登录状态, 返回是否全部有效
+async fn check_login(accounts: Vec<Account>) -> bool {
+    let handlers = accounts.into_iter().map(|account| {
+        tokio::spawn(async move {
+            let remark = account.remark.clone();
+            let result = match dm::DmTicket::new(account).await {
+                Ok(dm_ticket) => dm_ticket.check_login().await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    info!("账号:{}, 登录有效.", remark);
+                    true
+                }
+                Err(e) => {
+                    error!("账号:{}, 登录检查失败, {}", remark, e);
+                    false
+                }
+            }
+        })
+    });
+    join_all(handlers)
+        .await
+        .into_iter()
+        .all(|ok| ok.unwrap_or(false))
+}
+
+async fn inspect(accounts: Vec<Account>, item_id: &str, format: OutputFormat) -> Result<()> {
+    let account = accounts
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("没有可用的账号"))?;
+    let item_id = resolve_item_id(item_id).await?;
+    let dm_ticket = dm::DmTicket::new(account).await?;
+    println!("{}", dm_ticket.inspect(&item_id).await?.render(format)?);
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -22,19 +176,65 @@ async fn main() -> Result<()> {
 
     pretty_env_logger::init();
 
-    let config: Config = load_global_config().unwrap();
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Run);
 
-    let mut handlers = Vec::new();
+    if let Command::Config {
+        command: ConfigCommand::Schema { path },
+    } = &command
+    {
+        let schema = serde_json::to_string_pretty(&config::schema())?;
+        match path {
+            Some(path) => std::fs::write(path, schema)?,
+            None => println!("{}", schema),
+        }
+        return Ok(());
+    }
 
-    for account in config.accounts.iter() {
-        let account = account.clone();
-        let handler = tokio::spawn(async move {
-            let dm_ticket = dm::DmTicket::new(account).await.unwrap();
-            dm_ticket.run().await.unwrap();
-        });
-        handlers.push(handler);
+    let config: Config = match load_config(&cli.config).await {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let accounts = match select_accounts(&config, &cli.account) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    match command {
+        Command::Run => run(accounts, false).await,
+        Command::DryRun => run(accounts, true).await,
+        Command::Inspect { item_id, json, csv } => {
+            let format = match (json, csv) {
+                (true, _) => OutputFormat::Json,
+                (_, true) => OutputFormat::Csv,
+                _ => OutputFormat::Table,
+            };
+            if let Err(e) = inspect(accounts, &item_id, format).await {
+                error!("查看演出:{}失败, {}", item_id, e);
+                process::exit(1);
+            }
+        }
+        Command::CheckLogin => {
+            if !check_login(accounts).await {
+                process::exit(1);
+            }
+        }
+        Command::ValidateConfig => {
+            info!(
+                "配置文件:{}校验通过, 共{}个账号.",
+                cli.config,
+                config.accounts.len()
+            );
+        }
+        Command::Config { .. } => {}
     }
-    join_all(handlers).await;
 
     Ok(())
 }
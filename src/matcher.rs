@@ -0,0 +1,522 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use chrono::NaiveDate;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::{
+    perform::Sku,
+    ticket::{Perform, PerformBase},
+};
+
+/// 场次选择: 序号(从1开始), 或者场次ID/日期/名称关键字
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum SessionMatcher {
+    /// 场次序号, 从1开始. 大于场次数量时按performId匹配
+    Index(#[schemars(range(min = 1))] usize),
+    /// performId、场次日期(YYYY-MM-DD)或场次名称关键字
+    Keyword(String),
+}
+
+impl fmt::Display for SessionMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(idx) => write!(f, "第{}个场次", idx),
+            Self::Keyword(keyword) => write!(f, "{}", keyword),
+        }
+    }
+}
+
+// 日期关键字在场次名称中可能出现的格式
+fn date_patterns(date: &NaiveDate) -> Vec<String> {
+    ["%Y-%m-%d", "%Y.%m.%d", "%Y/%m/%d", "%m月%d日", "%-m月%-d日"]
+        .iter()
+        .map(|f| date.format(f).to_string())
+        .collect()
+}
+
+fn describe_performs<'a>(performs: impl Iterator<Item = &'a Perform>) -> String {
+    performs
+        .map(|p| format!("\t{} (performId: {})", p.perform_name, p.perform_id))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// 第i个场次组(从0开始)在sessions中的序号. 与SessionMatcher::Index一致,
+// 包含多个场次的场次组无法通过序号选择, 返回None
+pub fn session_index(i: usize, base: &PerformBase) -> Option<usize> {
+    (base.performs.len() == 1).then_some(i + 1)
+}
+
+// 可选场次列表. 无法通过序号选择的场次序号显示为-
+fn describe_sessions(bases: &[PerformBase]) -> String {
+    let mut lines = Vec::new();
+    for (i, base) in bases.iter().enumerate() {
+        let index = session_index(i, base)
+            .map(|index| index.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        for perform in base.performs.iter() {
+            lines.push(format!(
+                "\t[{}] {} (performId: {})",
+                index, perform.perform_name, perform.perform_id
+            ));
+        }
+    }
+    lines.join("\n")
+}
+
+impl SessionMatcher {
+    // 按配置选择场次
+    pub fn resolve<'a>(&self, bases: &'a [PerformBase]) -> Result<&'a Perform> {
+        let performs = bases
+            .iter()
+            .flat_map(|base| base.performs.iter().map(move |p| (base, p)))
+            .collect::<Vec<_>>();
+
+        let keyword = match self {
+            // 未加引号的performId(如: sessions: 211234567890)会解析为序号, 大于场次数量时按performId匹配
+            Self::Index(idx) if *idx > bases.len() => {
+                let perform_id = idx.to_string();
+                return performs
+                    .iter()
+                    .find(|(_, p)| p.perform_id == perform_id)
+                    .map(|(_, p)| *p)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "未找到{}或performId为{}的场次, 可选场次:\n{}",
+                            self,
+                            perform_id,
+                            describe_sessions(bases)
+                        )
+                    });
+            }
+            Self::Index(idx) => {
+                let base = idx
+                    .checked_sub(1)
+                    .and_then(|i| bases.get(i))
+                    .ok_or_else(|| {
+                        anyhow!("未找到{}, 可选场次:\n{}", self, describe_sessions(bases))
+                    })?;
+                return match base.performs.as_slice() {
+                    [perform] => Ok(perform),
+                    [] => Err(anyhow!(
+                        "{}没有可选场次, 可选场次:\n{}",
+                        self,
+                        describe_sessions(bases)
+                    )),
+                    list => Err(anyhow!(
+                        "{}包含多个场次, 请使用performId或场次名称选择:\n{}",
+                        self,
+                        describe_performs(list.iter())
+                    )),
+                };
+            }
+            Self::Keyword(keyword) => keyword.trim(),
+        };
+
+        // 场次ID精确匹配
+        if let Some((_, perform)) = performs.iter().find(|(_, p)| p.perform_id == keyword) {
+            return Ok(perform);
+        }
+
+        let matched = match NaiveDate::parse_from_str(keyword, "%Y-%m-%d") {
+            Ok(date) => {
+                let patterns = date_patterns(&date);
+                performs
+                    .iter()
+                    .filter(|(base, p)| {
+                        patterns.iter().any(|pat| {
+                            p.perform_name.contains(pat)
+                                || base.name.contains(pat)
+                                || base.time_span.contains(pat)
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }
+            Err(_) => performs
+                .iter()
+                .filter(|(_, p)| p.perform_name.contains(keyword))
+                .collect::<Vec<_>>(),
+        };
+
+        match matched.as_slice() {
+            [(_, perform)] => Ok(perform),
+            [] => Err(anyhow!(
+                "未找到匹配的场次: {}, 可选场次:\n{}",
+                self,
+                describe_sessions(bases)
+            )),
+            _ => Err(anyhow!(
+                "场次: {} 匹配到多个场次, 请使用更精确的名称或performId:\n{}",
+                self,
+                describe_performs(matched.iter().map(|(_, p)| *p))
+            )),
+        }
+    }
+}
+
+/// 票档选择: 序号(从1开始), 价格/票档名称/skuId, 或者关键字
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GradeMatcher {
+    /// 票档序号, 从1开始. 大于票档数量时按价格匹配
+    Index(#[schemars(range(min = 1))] usize),
+    /// 按价格匹配
+    Price { price: f64 },
+    /// 按票档名称关键字匹配
+    PriceName { price_name: String },
+    /// 按skuId匹配
+    SkuId { sku_id: String },
+    /// skuId、价格或票档名称关键字
+    Keyword(String),
+}
+
+impl fmt::Display for GradeMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(idx) => write!(f, "第{}个票档或价格{}", idx, idx),
+            Self::Price { price } => write!(f, "价格{}", price),
+            Self::PriceName { price_name } => write!(f, "票档名称{}", price_name),
+            Self::SkuId { sku_id } => write!(f, "skuId {}", sku_id),
+            Self::Keyword(keyword) => write!(f, "{}", keyword),
+        }
+    }
+}
+
+fn describe_skus(skus: &[Sku]) -> String {
+    skus.iter()
+        .enumerate()
+        .map(|(i, sku)| {
+            format!(
+                "\t[{}] {} 价格:{} (skuId: {})",
+                i + 1,
+                sku.price_name,
+                sku.price,
+                sku.sku_id
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn price_eq(sku: &Sku, price: f64) -> bool {
+    sku.price
+        .trim()
+        .parse::<f64>()
+        .map(|p| (p - price).abs() < 0.001)
+        .unwrap_or(false)
+}
+
+impl GradeMatcher {
+    // 按配置选择票档
+    pub fn resolve<'a>(&self, skus: &'a [Sku]) -> Result<&'a Sku> {
+        let matched = match self {
+            // 未加引号的价格(如: grade: 680)会解析为序号, 大于票档数量时按价格匹配
+            Self::Index(idx) if *idx > skus.len() => {
+                skus.iter().filter(|s| price_eq(s, *idx as f64)).collect()
+            }
+            Self::Index(idx) => idx
+                .checked_sub(1)
+                .and_then(|i| skus.get(i))
+                .into_iter()
+                .collect::<Vec<_>>(),
+            Self::Price { price } => skus.iter().filter(|s| price_eq(s, *price)).collect(),
+            Self::PriceName { price_name } => skus
+                .iter()
+                .filter(|s| s.price_name.contains(price_name.trim()))
+                .collect(),
+            Self::SkuId { sku_id } => skus.iter().filter(|s| &s.sku_id == sku_id).collect(),
+            Self::Keyword(keyword) => {
+                let keyword = keyword.trim();
+                match skus.iter().find(|s| s.sku_id == keyword) {
+                    Some(sku) => vec![sku],
+                    None => match keyword.parse::<f64>() {
+                        Ok(price) => skus.iter().filter(|s| price_eq(s, price)).collect(),
+                        Err(_) => skus
+                            .iter()
+                            .filter(|s| s.price_name.contains(keyword))
+                            .collect(),
+                    },
+                }
+            }
+        };
+
+        match matched.as_slice() {
+            [sku] => Ok(sku),
+            [] => Err(anyhow!(
+                "未找到匹配的票档: {}, 可选票档:\n{}",
+                self,
+                describe_skus(skus)
+            )),
+            _ => Err(anyhow!(
+                "票档: {} 匹配到多个票档, 请使用更精确的票档名称或skuId:\n{}",
+                self,
+                matched
+                    .iter()
+                    .map(|s| format!("\t{} 价格:{} (skuId: {})", s.price_name, s.price, s.sku_id))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )),
+        }
+    }
+}
+
+// 实名观演人描述, 如: 张三(****1234)
+pub fn describe_viewer(viewer: &Value) -> String {
+    let cert_no = viewer["certNo"].as_str().unwrap_or_default();
+    let suffix = cert_no
+        .char_indices()
+        .rev()
+        .nth(3)
+        .map(|(i, _)| &cert_no[i..])
+        .unwrap_or(cert_no);
+    format!(
+        "{}(****{})",
+        viewer["viewerName"].as_str().unwrap_or_default(),
+        suffix
+    )
+}
+
+// 按姓名或证件号后4位选择实名观演人, 返回viewerList中的索引
+pub fn match_viewers(keywords: &[String], viewer_list: &[Value]) -> Result<Vec<usize>> {
+    let mut selected = Vec::new();
+    for keyword in keywords.iter().map(|k| k.trim()) {
+        let matched = viewer_list
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| {
+                v["viewerName"].as_str() == Some(keyword)
+                    || v["certNo"]
+                        .as_str()
+                        .map(|c| c.to_uppercase().ends_with(&keyword.to_uppercase()))
+                        .unwrap_or(false)
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+
+        let available = viewer_list
+            .iter()
+            .map(describe_viewer)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match matched.as_slice() {
+            [idx] if !selected.contains(idx) => selected.push(*idx),
+            [_] => return Err(anyhow!("实名观演人: {} 重复选择", keyword)),
+            [] => {
+                return Err(anyhow!(
+                    "未找到实名观演人: {}, 可选观演人: {}",
+                    keyword,
+                    available
+                ))
+            }
+            _ => {
+                return Err(anyhow!(
+                    "实名观演人: {} 匹配到多个观演人, 请使用姓名或证件号后4位: {}",
+                    keyword,
+                    available
+                ))
+            }
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bases() -> Vec<PerformBase> {
+        let sessions: [(&str, &[(&str, &str)]); 6] = [
+            (
+                "2026-11-01 周六",
+                &[("211000000001", "2026-11-01 周六 19:30")],
+            ),
+            (
+                "2026-11-02 周日",
+                &[
+                    ("211000000002", "11月2日 下午场 14:00"),
+                    ("211000000003", "11月2日 晚场 19:30"),
+                ],
+            ),
+            (
+                "2026.11.08 周日",
+                &[("211000000004", "2026.11.08 周日 加场")],
+            ),
+            (
+                "2026/11/09 周一",
+                &[("211000000005", "2026/11/09 周一 20:00")],
+            ),
+            ("12月05日 周六", &[("211000000006", "12月05日 周六 19:00")]),
+            ("3月7日 周日", &[("211000000007", "3月7日 周日 18:00")]),
+        ];
+        sessions
+            .iter()
+            .map(|(name, performs)| PerformBase {
+                name: name.to_string(),
+                time_span: name.to_string(),
+                perform_base_tag_desc: String::new(),
+                performs: performs
+                    .iter()
+                    .map(|(perform_id, perform_name)| Perform {
+                        perform_id: perform_id.to_string(),
+                        item_id: "710947802955".to_string(),
+                        perform_name: perform_name.to_string(),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolve_session() {
+        let bases = bases();
+        // (配置, 选中的performId或错误信息)
+        let cases = [
+            ("1", Ok("211000000001")),
+            ("3", Ok("211000000004")),
+            ("2", Err("包含多个场次")),
+            ("0", Err("未找到第0个场次")),
+            ("211000000003", Ok("211000000003")),
+            ("\"211000000005\"", Ok("211000000005")),
+            ("211000000009", Err("未找到第211000000009个场次或performId")),
+            ("\"2026-11-01\"", Ok("211000000001")),
+            ("\"2026-11-08\"", Ok("211000000004")),
+            ("\"2026-11-09\"", Ok("211000000005")),
+            ("\"2026-12-05\"", Ok("211000000006")),
+            ("\"2027-03-07\"", Ok("211000000007")),
+            ("\"2026-11-02\"", Err("匹配到多个场次")),
+            ("\"2026-11-03\"", Err("未找到匹配的场次")),
+            ("晚场", Ok("211000000003")),
+            ("加场", Ok("211000000004")),
+            ("19:30", Err("匹配到多个场次")),
+            ("不存在", Err("未找到匹配的场次")),
+        ];
+
+        for (yaml, expected) in cases {
+            let matcher = serde_yaml::from_str::<SessionMatcher>(yaml).unwrap();
+            match (matcher.resolve(&bases), expected) {
+                (Ok(perform), Ok(perform_id)) => {
+                    assert_eq!(perform.perform_id, perform_id, "sessions: {}", yaml)
+                }
+                (Err(e), Err(msg)) => {
+                    assert!(e.to_string().contains(msg), "sessions: {}, {}", yaml, e)
+                }
+                (res, expected) => panic!(
+                    "sessions: {}, {:?}, expected: {:?}",
+                    yaml,
+                    res.map(|p| &p.perform_id),
+                    expected
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn describe_unindexed_sessions() {
+        let lines = describe_sessions(&bases());
+        assert!(lines.contains("[1] 2026-11-01 周六 19:30"));
+        assert!(lines.contains("[-] 11月2日 晚场 19:30"));
+        assert!(lines.contains("[3] 2026.11.08 周日 加场"));
+
+        let indexes = bases()
+            .iter()
+            .enumerate()
+            .map(|(i, base)| session_index(i, base))
+            .collect::<Vec<_>>();
+        assert_eq!(indexes[..3], [Some(1), None, Some(3)]);
+    }
+
+    fn skus() -> Vec<Sku> {
+        [
+            ("看台380元", "380"),
+            ("看台680元", "680"),
+            ("内场1280元", "1280"),
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, (name, price))| Sku {
+            sku_id: format!("50000000{}", i),
+            item_id: "710947802955".to_string(),
+            price_name: name.to_string(),
+            price: price.to_string(),
+        })
+        .collect()
+    }
+
+    #[test]
+    fn resolve_grade() {
+        let skus = skus();
+        let cases = [
+            ("2", "看台680元"),
+            ("680", "看台680元"),
+            ("\"680\"", "看台680元"),
+            ("{price: 1280}", "内场1280元"),
+            ("{price_name: 内场}", "内场1280元"),
+            ("\"500000000\"", "看台380元"),
+            ("内场", "内场1280元"),
+        ];
+
+        for (yaml, expected) in cases {
+            let matcher = serde_yaml::from_str::<GradeMatcher>(yaml).unwrap();
+            let sku = matcher.resolve(&skus).unwrap();
+            assert_eq!(sku.price_name, expected, "grade: {}", yaml);
+        }
+
+        for yaml in ["4", "999", "\"看台\""] {
+            let matcher = serde_yaml::from_str::<GradeMatcher>(yaml).unwrap();
+            assert!(matcher.resolve(&skus).is_err(), "grade: {}", yaml);
+        }
+    }
+
+    #[test]
+    fn select_viewers() {
+        let viewer_list = [
+            ("张三", "110101199001011234"),
+            ("李四", "11010119900202567X"),
+            ("王五", "110101199003031234"),
+            ("赵六", "110101199004045678"),
+        ]
+        .iter()
+        .map(|(name, cert_no)| serde_json::json!({"viewerName": name, "certNo": cert_no}))
+        .collect::<Vec<_>>();
+
+        // (配置, 选中的索引或错误信息)
+        let cases = [
+            (vec!["张三"], Ok(vec![0])),
+            (vec!["赵六", " 李四 "], Ok(vec![3, 1])),
+            (vec!["5678"], Ok(vec![3])),
+            (vec!["567X"], Ok(vec![1])),
+            (vec!["567x"], Ok(vec![1])),
+            (vec!["赵六", "5678"], Err("重复选择")),
+            (vec!["1234"], Err("匹配到多个观演人")),
+            (vec!["孙七"], Err("未找到实名观演人: 孙七")),
+        ];
+
+        for (keywords, expected) in cases {
+            let keywords = keywords.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+            match (match_viewers(&keywords, &viewer_list), expected) {
+                (Ok(selected), Ok(expected)) => {
+                    assert_eq!(selected, expected, "viewers: {:?}", keywords)
+                }
+                (Err(e), Err(msg)) => {
+                    assert!(
+                        e.to_string().contains(msg),
+                        "viewers: {:?}, {}",
+                        keywords,
+                        e
+                    )
+                }
+                (res, expected) => panic!(
+                    "viewers: {:?}, {:?}, expected: {:?}",
+                    keywords,
+                    res.map_err(|e| e.to_string()),
+                    expected
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,844 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use chrono::{Local, TimeZone, Utc};
+use hyper::{
+    body,
+    header::{CONTENT_TYPE, COOKIE, DATE, SET_COOKIE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+
+use crate::{
+    clock::NTP_UNIX_OFFSET_SECS,
+    models::{
+        order::{
+            OrderInfo, OrderInfoEndpoint, OrderInfoGlobal, OrderInfoHierarchy, OrderInfoLinkage,
+            OrderInfoLinkageCommon,
+        },
+        perform::{Perform, PerformInfo, Sku},
+        ticket::{
+            DetailViewComponentItem, DetailViewComponentMap, Perform as TicketPerform, PerformBase,
+            StaticData, StaticDataItemBase, TicketDetail, TicketInfo,
+        },
+        DmRes,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockSku {
+    pub name: String,
+    pub price: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MockPerform {
+    pub name: String,
+    pub skus: Vec<MockSku>,
+}
+
+// mock服务的演练脚本
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MockScenario {
+    // 监听地址
+    pub listen: String,
+
+    // 服务启动后多少秒开售
+    pub sell_start_after: i64,
+
+    // 开售时间戳(毫秒), 设置后忽略sell_start_after
+    pub sell_start_timestamp: Option<i64>,
+
+    // 库存, 不设置则不限量, 为0时返回售罄
+    pub stock: Option<u64>,
+
+    // _m_h5_tk有效期(秒), 过期后返回令牌过期
+    pub token_ttl: Option<i64>,
+
+    // 开售后前N次生成订单请求返回限流
+    pub throttle_first: u64,
+
+    // 前N次提交订单请求返回限流
+    pub create_throttle_first: u64,
+
+    // 售罄的票档名称
+    pub sold_out: Vec<String>,
+
+    // SNTP服务监听地址, 如: 127.0.0.1:1123
+    pub ntp_listen: Option<String>,
+
+    // 订单创建后多少秒变为已付款, 不设置则一直待付款
+    pub pay_after: Option<i64>,
+
+    // 订单付款时限(秒), 超时未付款则订单关闭
+    pub order_ttl: i64,
+
+    // 登录失效, 需要登录的接口返回FAIL_SYS_SESSION_EXPIRED
+    pub session_expired: bool,
+
+    pub item_name: String,
+    pub performs: Vec<MockPerform>,
+    pub viewers: Vec<String>,
+}
+
+impl Default for MockScenario {
+    fn default() -> Self {
+        let skus = vec![
+            MockSku {
+                name: "看台380元".to_string(),
+                price: "380".to_string(),
+            },
+            MockSku {
+                name: "看台680元".to_string(),
+                price: "680".to_string(),
+            },
+            MockSku {
+                name: "内场1280元".to_string(),
+                price: "1280".to_string(),
+            },
+        ];
+        Self {
+            listen: "127.0.0.1:8081".to_string(),
+            sell_start_after: 30,
+            sell_start_timestamp: None,
+            stock: None,
+            token_ttl: None,
+            throttle_first: 0,
+            create_throttle_first: 0,
+            sold_out: vec![],
+            ntp_listen: None,
+            pay_after: None,
+            order_ttl: 900,
+            session_expired: false,
+            item_name: "模拟演唱会".to_string(),
+            performs: vec![
+                MockPerform {
+                    name: "2026-11-01 周六 19:30".to_string(),
+                    skus: skus.clone(),
+                },
+                MockPerform {
+                    name: "2026-11-02 周日 19:30".to_string(),
+                    skus,
+                },
+            ],
+            viewers: vec!["张三".to_string(), "李四".to_string()],
+        }
+    }
+}
+
+impl MockScenario {
+    pub fn load(path: &str) -> Result<Self> {
+        Ok(serde_yaml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+
+// 已创建的订单
+#[derive(Debug, Clone)]
+pub struct MockOrder {
+    pub buy_param: String,
+    pub viewers: Vec<String>,
+    pub created_at: i64,
+}
+
+pub struct MockState {
+    pub scenario: MockScenario,
+    orders: Mutex<HashMap<String, MockOrder>>,
+    pub sell_start_timestamp: i64,
+    build_count: AtomicU64,
+    create_count: AtomicU64,
+    order_count: AtomicU64,
+}
+
+impl MockState {
+    pub fn new(scenario: MockScenario) -> Self {
+        let sell_start_timestamp = scenario
+            .sell_start_timestamp
+            .unwrap_or_else(|| Local::now().timestamp_millis() + scenario.sell_start_after * 1000);
+        Self {
+            scenario,
+            orders: Mutex::new(HashMap::new()),
+            sell_start_timestamp,
+            build_count: AtomicU64::new(0),
+            create_count: AtomicU64::new(0),
+            order_count: AtomicU64::new(0),
+        }
+    }
+
+    fn ticket_info(&self, item_id: &str) -> TicketInfo {
+        let sell_start_time_str = Local
+            .timestamp_millis_opt(self.sell_start_timestamp)
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+
+        let perform_bases = self
+            .scenario
+            .performs
+            .iter()
+            .enumerate()
+            .map(|(i, p)| PerformBase {
+                name: p.name.clone(),
+                time_span: p.name.clone(),
+                perform_base_tag_desc: "".to_string(),
+                performs: vec![TicketPerform {
+                    perform_id: perform_id(i),
+                    item_id: item_id.to_string(),
+                    perform_name: p.name.clone(),
+                }],
+            })
+            .collect();
+
+        TicketInfo {
+            detail_view_component_map: DetailViewComponentMap {
+                atmosphere: json!({}),
+                item: DetailViewComponentItem {
+                    static_data: StaticData {
+                        item_base: StaticDataItemBase {
+                            item_id: item_id.to_string(),
+                            item_name: self.scenario.item_name.clone(),
+                        },
+                    },
+                    dynamic_ext_data: json!({}),
+                    item: TicketDetail {
+                        sell_start_timestamp: self.sell_start_timestamp.to_string(),
+                        sell_start_time_str,
+                        perform_bases,
+                    },
+                },
+            },
+        }
+    }
+
+    fn perform_info(&self, item_id: &str, perform_id: &str) -> Option<PerformInfo> {
+        let idx = self
+            .scenario
+            .performs
+            .iter()
+            .enumerate()
+            .position(|(i, _)| self::perform_id(i) == perform_id)?;
+        let perform = &self.scenario.performs[idx];
+        Some(PerformInfo {
+            perform: Perform {
+                perform_id: perform_id.to_string(),
+                perform_name: perform.name.clone(),
+                sku_list: perform
+                    .skus
+                    .iter()
+                    .enumerate()
+                    .map(|(i, s)| Sku {
+                        sku_id: sku_id(idx, i),
+                        item_id: item_id.to_string(),
+                        price_name: s.name.clone(),
+                        price: s.price.clone(),
+                    })
+                    .collect(),
+            },
+        })
+    }
+
+    fn sku_price(&self, sku_id: &str) -> Option<(String, String)> {
+        self.scenario
+            .performs
+            .iter()
+            .enumerate()
+            .find_map(|(p, perform)| {
+                perform.skus.iter().enumerate().find_map(|(i, sku)| {
+                    (self::sku_id(p, i) == sku_id).then(|| (sku.name.clone(), sku.price.clone()))
+                })
+            })
+    }
+
+    fn order_detail(&self, order_id: &str) -> Option<Value> {
+        let order = self.orders.lock().unwrap().get(order_id).cloned()?;
+
+        // buyParam: itemId_num_skuId
+        let parts = order.buy_param.split('_').collect::<Vec<_>>();
+        let num = parts
+            .get(1)
+            .and_then(|n| n.parse::<f64>().ok())
+            .unwrap_or(1.0);
+        let (sku_name, price) = self
+            .sku_price(parts.last().copied().unwrap_or_default())
+            .unwrap_or_default();
+        let amount = price.parse::<f64>().unwrap_or_default() * num;
+
+        let now = Local::now().timestamp_millis();
+        let pay_deadline = order.created_at + self.scenario.order_ttl * 1000;
+        let status = match self.scenario.pay_after {
+            Some(secs) if order.created_at + secs * 1000 <= now.min(pay_deadline) => "交易成功",
+            _ if now >= pay_deadline => "交易关闭",
+            _ => "待付款",
+        };
+
+        Some(json!({
+            "orderId": order_id,
+            "orderStatusDesc": status,
+            "itemName": self.scenario.item_name,
+            "skuName": sku_name,
+            "totalAmount": format!("{:.2}", amount),
+            "payDeadline": pay_deadline.to_string(),
+            "viewers": order.viewers.iter().map(|v| json!({"viewerName": v})).collect::<Vec<_>>(),
+        }))
+    }
+
+    // buyParam: itemId_num_skuId
+    fn is_sold_out(&self, buy_param: &str) -> bool {
+        let sku_id = buy_param.rsplit('_').next().unwrap_or_default();
+        self.scenario
+            .performs
+            .iter()
+            .enumerate()
+            .any(|(p, perform)| {
+                perform.skus.iter().enumerate().any(|(i, sku)| {
+                    self::sku_id(p, i) == sku_id && self.scenario.sold_out.contains(&sku.name)
+                })
+            })
+    }
+
+    fn order_info(&self, buy_param: &str) -> OrderInfo {
+        let viewer_list = self
+            .scenario
+            .viewers
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                json!({
+                    "viewerId": format!("{}", 1000 + i),
+                    "viewerName": name,
+                    "certNo": format!("110101********{:04}", 1000 + i),
+                    "isUsed": false,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let data = json!({
+            "confirmOrder_1": {"tag": "confirmOrder", "fields": {}},
+            "order_1": {"tag": "order", "fields": {"buyParam": buy_param}},
+            "item_1": {"tag": "item", "fields": {"buyParam": buy_param}},
+            "dmViewer_1": {"tag": "dmViewer", "fields": {"viewerList": viewer_list}},
+        });
+
+        OrderInfo {
+            data,
+            endpoint: OrderInfoEndpoint {},
+            global: OrderInfoGlobal {
+                secret_key: "submitref".to_string(),
+                secret_value: format!("mock-{}", Local::now().timestamp_millis()),
+            },
+            hierarchy: OrderInfoHierarchy {
+                component: vec![
+                    "confirmOrder".to_string(),
+                    "order".to_string(),
+                    "item".to_string(),
+                    "dmViewer".to_string(),
+                ],
+                root: "confirmOrder_1".to_string(),
+                base_type: vec![],
+                structure: json!({
+                    "confirmOrder_1": ["order_1", "dmViewer_1"],
+                    "order_1": ["item_1"],
+                }),
+            },
+            linkage: OrderInfoLinkage {
+                input: vec!["dmViewer_1".to_string()],
+                request: vec!["dmViewer_1".to_string()],
+                signature: "mock-signature".to_string(),
+                common: OrderInfoLinkageCommon {
+                    query_params: "".to_string(),
+                    compress: false,
+                    validate_params: "".to_string(),
+                    structures: "".to_string(),
+                    submit_params: "".to_string(),
+                },
+            },
+        }
+    }
+}
+
+fn perform_id(idx: usize) -> String {
+    format!("{}", 200000 + idx)
+}
+
+fn sku_id(perform_idx: usize, sku_idx: usize) -> String {
+    format!("{}", 500000 + perform_idx * 100 + sku_idx)
+}
+
+fn success(api: &str, data: Value) -> DmRes {
+    DmRes {
+        api: Some(api.to_string()),
+        data,
+        ret: vec![crate::error::SUCCESS_FLAG.to_string()],
+        v: None,
+    }
+}
+
+fn failure(api: &str, ret: &str) -> DmRes {
+    DmRes {
+        api: Some(api.to_string()),
+        data: json!({}),
+        ret: vec![ret.to_string()],
+        v: None,
+    }
+}
+
+// 解析提交订单的数据: params => data => order_*/dmViewer_*
+fn submitted_order(data: &Value, now: i64) -> MockOrder {
+    let parse = |v: &Value| -> Value {
+        serde_json::from_str(v.as_str().unwrap_or("{}")).unwrap_or_default()
+    };
+    let params = parse(&data["params"]);
+    let order_data = parse(&params["data"]);
+
+    let mut buy_param = String::new();
+    let mut viewers = Vec::new();
+    if let Some(map) = order_data.as_object() {
+        for (key, component) in map {
+            if key.starts_with("order_") {
+                buy_param = component["fields"]["buyParam"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+            }
+            if key.starts_with("dmViewer_") {
+                viewers.extend(
+                    component["fields"]["viewerList"]
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter(|v| v["isUsed"].as_bool().unwrap_or(false))
+                        .filter_map(|v| v["viewerName"].as_str().map(|n| n.to_string())),
+                );
+            }
+        }
+    }
+
+    MockOrder {
+        buy_param,
+        viewers,
+        created_at: now,
+    }
+}
+
+// 从cookie中解析_m_h5_tk的签发时间
+fn token_issued_at(cookie: &str) -> Option<i64> {
+    cookie
+        .split(';')
+        .filter_map(|kv| kv.trim().split_once('='))
+        .find(|(k, _)| *k == "_m_h5_tk")
+        .and_then(|(_, v)| v.rsplit('_').next())
+        .and_then(|t| t.parse::<i64>().ok())
+}
+
+// 解析接口名称, 如: /h5/mtop.trade.order.build.h5/4.0/ => mtop.trade.order.build.h5
+fn api_name(path: &str) -> String {
+    path.trim_start_matches('/')
+        .split('/')
+        .nth(1)
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn handle_mtop(state: &MockState, api: &str, cookie: &str, data: Value) -> DmRes {
+    let now = Local::now().timestamp_millis();
+
+    if let Some(ttl) = state.scenario.token_ttl {
+        match token_issued_at(cookie) {
+            Some(issued_at) if now - issued_at <= ttl * 1000 => {}
+            _ => return failure(api, "FAIL_SYS_TOKEN_EXOIRED::令牌过期"),
+        }
+    }
+
+    let item_id = data["itemId"]
+        .as_str()
+        .unwrap_or("710947802955")
+        .to_string();
+
+    match api {
+        "mtop.alibaba.damai.detail.getdetail" => {
+            let ticket_info = state.ticket_info(&item_id);
+            success(
+                api,
+                json!({"result": serde_json::to_string(&ticket_info).unwrap()}),
+            )
+        }
+        "mtop.alibaba.detail.subpage.getdetail" => {
+            let ex_params: Value =
+                serde_json::from_str(data["exParams"].as_str().unwrap_or("{}")).unwrap_or_default();
+            let perform_id = ex_params["dataId"].as_str().unwrap_or_default();
+            match state.perform_info(&item_id, perform_id) {
+                Some(info) => success(
+                    api,
+                    json!({"result": serde_json::to_string(&info).unwrap()}),
+                ),
+                None => failure(api, "B-00203-200-002::场次不存在"),
+            }
+        }
+        "mtop.trade.order.build.h5"
+        | "mtop.trade.order.create.h5"
+        | "mtop.damai.wireless.order.orderdetail"
+            if state.scenario.session_expired =>
+        {
+            failure(api, "FAIL_SYS_SESSION_EXPIRED::Session过期")
+        }
+        "mtop.trade.order.build.h5" => {
+            if now < state.sell_start_timestamp {
+                return failure(api, "B-00203-200-008::该商品还未开售");
+            }
+            if state.build_count.fetch_add(1, Ordering::SeqCst) < state.scenario.throttle_first {
+                return failure(api, "RGV587_ERROR::SM::哎哟喂,被挤爆啦,请稍后重试!");
+            }
+            let buy_param = data["buyParam"].as_str().unwrap_or_default();
+            if state.scenario.stock == Some(0) || state.is_sold_out(buy_param) {
+                return failure(api, "B-00203-200-001::库存不足");
+            }
+            success(
+                api,
+                serde_json::to_value(state.order_info(buy_param)).unwrap(),
+            )
+        }
+        "mtop.trade.order.create.h5" => {
+            if state.create_count.fetch_add(1, Ordering::SeqCst)
+                < state.scenario.create_throttle_first
+            {
+                return failure(api, "RGV587_ERROR::SM::哎哟喂,被挤爆啦,请稍后重试!");
+            }
+            let count = state.order_count.fetch_add(1, Ordering::SeqCst);
+            if matches!(state.scenario.stock, Some(stock) if count >= stock) {
+                return failure(api, "B-00203-200-001::库存不足");
+            }
+            let order_id = format!("{}", 9000000 + count);
+            state
+                .orders
+                .lock()
+                .unwrap()
+                .insert(order_id.clone(), submitted_order(&data, now));
+            success(api, json!({"bizOrderId": order_id}))
+        }
+        "mtop.damai.wireless.order.orderdetail" => {
+            let order_id = data["orderId"].as_str().unwrap_or_default();
+            match state.order_detail(order_id) {
+                Some(detail) => success(api, detail),
+                None => failure(api, "B-00203-200-404::订单不存在"),
+            }
+        }
+        _ => failure(api, "FAIL_SYS_API_NOT_FOUNDED::请求API不存在"),
+    }
+}
+
+async fn handle(state: Arc<MockState>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let path = req.uri().path().to_string();
+    let query: HashMap<String, String> =
+        serde_urlencoded::from_str(req.uri().query().unwrap_or_default()).unwrap_or_default();
+    let cookie = req
+        .headers()
+        .get(COOKIE)
+        .and_then(|c| c.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+
+    // 兼容token-server: GET /?key=bx_ua
+    if path == "/" {
+        let value = query.get("key").cloned().unwrap_or_default();
+        let res = json!({"code": 200, "data": {"value": format!("mock_{}", value)}});
+        return Ok(json_response(&res));
+    }
+
+    let api = api_name(&path);
+
+    // 获取_m_h5_tk
+    if api == "mtop.damai.wireless.search.broadcast.list" {
+        let now = Local::now().timestamp_millis();
+        let res = Response::builder()
+            .header(
+                SET_COOKIE,
+                format!("_m_h5_tk=mocktoken{}_{}; Path=/", now, now),
+            )
+            .header(SET_COOKIE, "_m_h5_tk_enc=mockenc; Path=/")
+            .header(DATE, http_date())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::to_string(&success(&api, json!({}))).unwrap(),
+            ))
+            .unwrap();
+        return Ok(res);
+    }
+
+    let bytes = body::to_bytes(req.into_body()).await.unwrap_or_default();
+    let form: HashMap<String, String> = serde_urlencoded::from_bytes(&bytes).unwrap_or_default();
+    let data: Value = form
+        .get("data")
+        .and_then(|d| serde_json::from_str(d).ok())
+        .unwrap_or_default();
+
+    let res = handle_mtop(&state, &api, &cookie, data);
+    debug!("mock {} => {:?}", api, res.ret);
+    Ok(json_response(&res))
+}
+
+// hyper缓存的Date不按整秒刷新, 校时演练需要准确的Date
+fn http_date() -> String {
+    Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+fn json_response<T: Serialize>(data: &T) -> Response<Body> {
+    Response::builder()
+        .header(DATE, http_date())
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(data).unwrap()))
+        .unwrap()
+}
+
+fn write_ntp_timestamp(buf: &mut [u8], millis: i64) {
+    let secs = (millis / 1000 + NTP_UNIX_OFFSET_SECS) as u32;
+    let frac = (((millis % 1000) << 32) / 1000) as u32;
+    buf[..4].copy_from_slice(&secs.to_be_bytes());
+    buf[4..8].copy_from_slice(&frac.to_be_bytes());
+}
+
+// 简易SNTP服务, 返回本机时间
+async fn serve_ntp(addr: String) -> Result<()> {
+    let socket = UdpSocket::bind(&addr).await?;
+    info!("mock SNTP服务已启动: {}", addr);
+
+    let mut buf = [0u8; 48];
+    loop {
+        let (_, peer) = socket.recv_from(&mut buf).await?;
+        let received = Local::now().timestamp_millis();
+
+        let mut response = [0u8; 48];
+        // LI=0, VN=3, Mode=4(server)
+        response[0] = 0x1c;
+        response[1] = 1;
+        response[24..32].copy_from_slice(&buf[40..48]);
+        write_ntp_timestamp(&mut response[32..40], received);
+        write_ntp_timestamp(&mut response[40..48], Local::now().timestamp_millis());
+        socket.send_to(&response, peer).await?;
+    }
+}
+
+// 启动mock服务, 直到进程退出
+pub async fn serve(scenario: MockScenario) -> Result<()> {
+    let addr: SocketAddr = scenario.listen.parse()?;
+
+    if let Some(ntp_addr) = scenario.ntp_listen.clone() {
+        tokio::spawn(async move {
+            if let Err(e) = serve_ntp(ntp_addr).await {
+                error!("mock SNTP服务异常, {}", e);
+            }
+        });
+    }
+
+    let state = Arc::new(MockState::new(scenario));
+
+    info!(
+        "mock服务已启动: http://{}/, 开售时间: {}",
+        addr,
+        Local
+            .timestamp_millis_opt(state.sell_start_timestamp)
+            .unwrap()
+            .format("%Y-%m-%d %H:%M:%S%.3f")
+    );
+
+    let make_svc = make_service_fn(move |_| {
+        let state = state.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req))) }
+    });
+
+    Server::bind(&addr).serve(make_svc).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD: &str = "mtop.trade.order.build.h5";
+    const CREATE: &str = "mtop.trade.order.create.h5";
+    const DETAIL: &str = "mtop.damai.wireless.order.orderdetail";
+    const THROTTLED: &str = "RGV587_ERROR::SM::哎哟喂,被挤爆啦,请稍后重试!";
+    const SOLD_OUT: &str = "B-00203-200-001::库存不足";
+
+    // 开售时间设为过去, 免得用例依赖墙钟
+    fn state(scenario: MockScenario) -> MockState {
+        MockState::new(MockScenario {
+            sell_start_timestamp: Some(0),
+            ..scenario
+        })
+    }
+
+    fn cookie(issued_at: i64) -> String {
+        format!(
+            "_m_h5_tk=mocktoken{}_{}; _m_h5_tk_enc=mockenc",
+            issued_at, issued_at
+        )
+    }
+
+    fn build(state: &MockState, sku_id: &str) -> String {
+        let data = json!({"itemId": "1", "buyParam": format!("1_1_{}", sku_id)});
+        handle_mtop(state, BUILD, "", data).ret[0].clone()
+    }
+
+    fn create(state: &MockState) -> String {
+        handle_mtop(state, CREATE, "", json!({})).ret[0].clone()
+    }
+
+    fn order_status(state: &MockState, created_at: i64) -> String {
+        state.orders.lock().unwrap().insert(
+            "1".to_string(),
+            MockOrder {
+                buy_param: "1_2_500001".to_string(),
+                viewers: vec![],
+                created_at,
+            },
+        );
+        let res = handle_mtop(state, DETAIL, "", json!({"orderId": "1"}));
+        res.data["orderStatusDesc"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn token_ttl() {
+        let now = Local::now().timestamp_millis();
+        let state = state(MockScenario {
+            token_ttl: Some(60),
+            ..Default::default()
+        });
+
+        let cases = [
+            ("".to_string(), "FAIL_SYS_TOKEN_EXOIRED::令牌过期"),
+            (cookie(now), crate::error::SUCCESS_FLAG),
+            (cookie(now - 120 * 1000), "FAIL_SYS_TOKEN_EXOIRED::令牌过期"),
+        ];
+        for (cookie, expected) in cases {
+            let res = handle_mtop(
+                &state,
+                "mtop.alibaba.damai.detail.getdetail",
+                &cookie,
+                json!({}),
+            );
+            assert_eq!(res.ret[0], expected, "cookie: {}", cookie);
+        }
+
+        assert_eq!(token_issued_at(&cookie(now)), Some(now));
+        assert_eq!(token_issued_at("_m_h5_tk_enc=mockenc"), None);
+    }
+
+    #[test]
+    fn stock() {
+        let state = state(MockScenario {
+            stock: Some(1),
+            sold_out: vec!["看台380元".to_string()],
+            ..Default::default()
+        });
+
+        assert_eq!(build(&state, "500000"), SOLD_OUT);
+        assert_eq!(build(&state, "500001"), crate::error::SUCCESS_FLAG);
+        assert_eq!(create(&state), crate::error::SUCCESS_FLAG);
+        assert_eq!(create(&state), SOLD_OUT);
+
+        let state = self::state(MockScenario {
+            stock: Some(0),
+            ..Default::default()
+        });
+        assert_eq!(build(&state, "500001"), SOLD_OUT);
+    }
+
+    #[test]
+    fn not_on_sale() {
+        let state = MockState::new(MockScenario::default());
+        assert_eq!(build(&state, "500001"), "B-00203-200-008::该商品还未开售");
+    }
+
+    #[test]
+    fn throttle_first() {
+        let state = state(MockScenario {
+            throttle_first: 2,
+            create_throttle_first: 1,
+            ..Default::default()
+        });
+
+        assert_eq!(build(&state, "500001"), THROTTLED);
+        assert_eq!(build(&state, "500001"), THROTTLED);
+        assert_eq!(build(&state, "500001"), crate::error::SUCCESS_FLAG);
+        assert_eq!(create(&state), THROTTLED);
+        assert_eq!(create(&state), crate::error::SUCCESS_FLAG);
+    }
+
+    #[test]
+    fn order_status_by_pay_after() {
+        let now = Local::now().timestamp_millis();
+
+        // (pay_after, order_ttl, 下单多少秒前, 订单状态)
+        let cases = [
+            (None, 900, 0, "待付款"),
+            (None, 900, 1000, "交易关闭"),
+            (Some(0), 900, 0, "交易成功"),
+            (Some(10), 900, 0, "待付款"),
+            (Some(10), 900, 20, "交易成功"),
+            (Some(10), 5, 20, "交易关闭"),
+        ];
+        for (pay_after, order_ttl, ago, expected) in cases {
+            let state = state(MockScenario {
+                pay_after,
+                order_ttl,
+                ..Default::default()
+            });
+            assert_eq!(
+                order_status(&state, now - ago * 1000),
+                expected,
+                "pay_after: {:?}, order_ttl: {}, ago: {}",
+                pay_after,
+                order_ttl,
+                ago
+            );
+        }
+    }
+
+    #[test]
+    fn order_detail() {
+        let state = state(MockScenario::default());
+        order_status(&state, Local::now().timestamp_millis());
+
+        let res = handle_mtop(&state, DETAIL, "", json!({"orderId": "1"}));
+        assert_eq!(res.data["skuName"], "看台680元");
+        assert_eq!(res.data["totalAmount"], "1360.00");
+
+        let res = handle_mtop(&state, DETAIL, "", json!({"orderId": "2"}));
+        assert_eq!(res.ret[0], "B-00203-200-404::订单不存在");
+    }
+
+    #[test]
+    fn parse_submitted_order() {
+        let order_data = json!({
+            "order_1": {"fields": {"buyParam": "1_2_500001"}},
+            "dmViewer_1": {"fields": {"viewerList": [
+                {"viewerName": "张三", "isUsed": true},
+                {"viewerName": "李四", "isUsed": false},
+                {"viewerName": "王五", "isUsed": true},
+            ]}},
+        });
+        let params = json!({"data": order_data.to_string()});
+        let data = json!({"params": params.to_string()});
+
+        let order = submitted_order(&data, 42);
+        assert_eq!(order.buy_param, "1_2_500001");
+        assert_eq!(order.viewers, vec!["张三", "王五"]);
+        assert_eq!(order.created_at, 42);
+
+        let order = submitted_order(&json!({}), 42);
+        assert!(order.buy_param.is_empty());
+        assert!(order.viewers.is_empty());
+    }
+}
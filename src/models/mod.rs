@@ -5,6 +5,8 @@ pub mod ticket;
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
 use serde_json::value;
+
+use crate::error::DmError;
 // cookie token.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DmToken {
@@ -22,6 +24,17 @@ pub struct DmRes {
     pub v: Option<String>,
 }
 
+impl DmRes {
+    // 解析ret, 调用成功时返回None
+    pub fn error(&self) -> Option<DmError> {
+        DmError::from_ret(&self.ret)
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error().is_none()
+    }
+}
+
 // 通用参数
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CommonParams {
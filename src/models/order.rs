@@ -1,4 +1,5 @@
 use anyhow::Result;
+use chrono::{Local, NaiveDateTime, TimeZone};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 
@@ -46,16 +47,16 @@ impl OrderForm {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoContainer {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoData {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoEndpoint {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoGlobal {
     #[serde(rename = "secretKey")]
     pub secret_key: String,
@@ -64,7 +65,7 @@ pub struct OrderInfoGlobal {
     pub secret_value: String, // submitref
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoHierarchy {
     pub component: Vec<String>,
     pub root: String,
@@ -75,7 +76,7 @@ pub struct OrderInfoHierarchy {
     pub structure: Value,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoLinkageCommon {
     #[serde(rename = "queryParams")]
     pub query_params: String,
@@ -91,7 +92,7 @@ pub struct OrderInfoLinkageCommon {
     pub submit_params: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfoLinkage {
     pub input: Vec<String>,
     pub request: Vec<String>,
@@ -99,7 +100,7 @@ pub struct OrderInfoLinkage {
     pub common: OrderInfoLinkageCommon,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OrderInfo {
     // pub container: OrderInfoContainer,
     pub data: Value,
@@ -127,3 +128,261 @@ impl SubmitOrderParams {
         Ok(params)
     }
 }
+
+pub struct OrderDetailParams;
+
+// 查询订单详情的请求参数
+impl OrderDetailParams {
+    pub fn build() -> Result<Value> {
+        let mut params = serde_json::to_value(CommonParams::build())?;
+        params["api"] = "mtop.damai.wireless.order.orderdetail".into();
+        params["v"] = "2.0".into();
+        params["ttid"] = "#t#ip##_h5_2014".into();
+        params["globalCode"] = "ali.china.damai".into();
+        Ok(params)
+    }
+}
+
+pub struct OrderDetailForm;
+
+impl OrderDetailForm {
+    pub fn build(order_id: &str) -> Result<Value> {
+        let data = json!({
+            "orderId": order_id,
+            "dmChannel": "damai@damaih5_h5"
+        });
+        Ok(data)
+    }
+}
+
+// 订单号字段路径, 按顺序查找
+const ORDER_ID_PATHS: &[&str] = &["/bizOrderId", "/orderId"];
+
+// 订单详情各字段路径, 只在已知路径下查找, 避免取到嵌套组件中无关的同名字段。
+// 仅用于付款监控, 未解析到任何字段时监控立即停止
+const ITEM_NAME_PATHS: &[&str] = &["/itemName"];
+const STATUS_PATHS: &[&str] = &["/orderStatusDesc"];
+const AMOUNT_PATHS: &[&str] = &["/totalAmount"];
+const PAY_DEADLINE_PATHS: &[&str] = &["/payDeadline"];
+const VIEWERS_PATHS: &[&str] = &["/viewers"];
+
+fn find_field<'a>(value: &'a Value, paths: &[&str]) -> Option<&'a Value> {
+    paths
+        .iter()
+        .find_map(|p| value.pointer(p).filter(|v| !v.is_null()))
+}
+
+fn field_string(value: &Value, paths: &[&str]) -> Option<String> {
+    find_field(value, paths).and_then(|v| match v {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+// 解析时间为毫秒时间戳, 支持秒/毫秒时间戳(数字或字符串)及"%Y-%m-%d %H:%M:%S"格式
+fn parse_timestamp(value: &Value) -> Option<i64> {
+    let ts = match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => match s.trim().parse::<i64>() {
+            Ok(ts) => Some(ts),
+            Err(_) => NaiveDateTime::parse_from_str(s.trim(), "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .and_then(|t| Local.from_local_datetime(&t).single())
+                .map(|t| t.timestamp_millis()),
+        },
+        _ => None,
+    }?;
+    // 10位为秒级时间戳
+    Some(if ts < 10_000_000_000 { ts * 1000 } else { ts })
+}
+
+// 从提交订单的返回数据中解析订单号
+pub fn parse_order_id(data: &Value) -> Option<String> {
+    field_string(data, ORDER_ID_PATHS).or_else(|| {
+        field_string(data, &["/orderIds"])
+            .and_then(|ids| ids.split(',').next().map(|id| id.trim().to_string()))
+            .filter(|id| !id.is_empty())
+    })
+}
+
+// 订单概要
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OrderSummary {
+    pub order_id: String,
+    pub item_name: Option<String>,
+    pub status: Option<String>,
+    pub amount: Option<String>,
+    // 付款截止时间戳(毫秒)
+    pub pay_deadline: Option<i64>,
+    pub viewers: Vec<String>,
+}
+
+impl OrderSummary {
+    pub fn from_detail(order_id: &str, data: &Value) -> Self {
+        let viewers = match find_field(data, VIEWERS_PATHS) {
+            Some(Value::Array(list)) => list
+                .iter()
+                .filter_map(|v| v["viewerName"].as_str().map(String::from))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            order_id: order_id.to_string(),
+            item_name: field_string(data, ITEM_NAME_PATHS),
+            status: field_string(data, STATUS_PATHS),
+            amount: field_string(data, AMOUNT_PATHS),
+            pay_deadline: find_field(data, PAY_DEADLINE_PATHS).and_then(parse_timestamp),
+            viewers,
+        }
+    }
+
+    // 没有解析到任何字段
+    pub fn is_empty(&self) -> bool {
+        self.item_name.is_none()
+            && self.status.is_none()
+            && self.amount.is_none()
+            && self.pay_deadline.is_none()
+            && self.viewers.is_empty()
+    }
+}
+
+// 订单付款状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderState {
+    Unpaid,
+    Paid,
+    Closed,
+    Unknown,
+}
+
+impl OrderSummary {
+    // 根据订单状态描述判断付款状态
+    pub fn state(&self) -> OrderState {
+        let status = self.status.as_deref().unwrap_or_default();
+        if ["关闭", "取消", "超时", "失效"]
+            .iter()
+            .any(|k| status.contains(k))
+        {
+            OrderState::Closed
+        } else if ["待付款", "等待付款", "未付款", "待支付"]
+            .iter()
+            .any(|k| status.contains(k))
+        {
+            OrderState::Unpaid
+        } else if [
+            "成功",
+            "已付款",
+            "已支付",
+            "待发货",
+            "已发货",
+            "出票",
+            "完成",
+        ]
+        .iter()
+        .any(|k| status.contains(k))
+        {
+            OrderState::Paid
+        } else {
+            OrderState::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_detail_known_paths() {
+        // 嵌套组件中的同名字段不应被取到
+        let data = json!({
+            "orderStatusDesc": "交易关闭",
+            "payDeadline": 1792325389,
+            "recommend": {
+                "itemName": "其他演出",
+                "totalAmount": "99.00",
+                "viewers": [{"viewerName": "李四"}],
+            },
+        });
+        let summary = OrderSummary::from_detail("1", &data);
+        assert_eq!(summary.item_name, None);
+        assert_eq!(summary.amount, None);
+        assert!(summary.viewers.is_empty());
+        assert_eq!(summary.pay_deadline, Some(1792325389000));
+        assert_eq!(summary.state(), OrderState::Closed);
+
+        let summary = OrderSummary::from_detail("1", &json!({}));
+        assert_eq!(summary.status, None);
+        assert_eq!(summary.pay_deadline, None);
+        assert_eq!(summary.state(), OrderState::Unknown);
+        assert!(summary.is_empty());
+    }
+
+    #[test]
+    fn pay_deadline() {
+        let local = |s| {
+            let t = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+            Local.from_local_datetime(&t).unwrap().timestamp_millis()
+        };
+        let cases = vec![
+            (json!(1792325389783i64), Some(1792325389783)),
+            (json!("1792325389783"), Some(1792325389783)),
+            (json!(1792325389), Some(1792325389000)),
+            (json!("1792325389"), Some(1792325389000)),
+            (
+                json!("2026-10-18 12:09:49"),
+                Some(local("2026-10-18 12:09:49")),
+            ),
+            (json!("明天"), None),
+            (json!(null), None),
+        ];
+        for (value, expected) in cases {
+            let data = json!({ "payDeadline": value });
+            assert_eq!(
+                OrderSummary::from_detail("1", &data).pay_deadline,
+                expected,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn state() {
+        let cases = [
+            ("待付款", OrderState::Unpaid),
+            ("等待付款", OrderState::Unpaid),
+            ("交易成功", OrderState::Paid),
+            ("已付款", OrderState::Paid),
+            ("待发货", OrderState::Paid),
+            ("交易关闭", OrderState::Closed),
+            ("超时取消", OrderState::Closed),
+            ("处理中", OrderState::Unknown),
+        ];
+        for (status, expected) in cases {
+            let summary = OrderSummary {
+                status: Some(status.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(summary.state(), expected, "{}", status);
+        }
+    }
+
+    #[test]
+    fn order_id() {
+        let cases = [
+            (json!({"bizOrderId": "9000000"}), Some("9000000")),
+            (json!({"bizOrderId": 9000000}), Some("9000000")),
+            (json!({"orderId": "9000001"}), Some("9000001")),
+            (json!({"orderIds": "9000002,9000003"}), Some("9000002")),
+            (json!({"orderIds": ""}), None),
+            (json!({"result": {"orderId": "9000004"}}), None),
+            (json!({}), None),
+        ];
+        for (data, expected) in cases {
+            assert_eq!(parse_order_id(&data).as_deref(), expected, "{}", data);
+        }
+    }
+}
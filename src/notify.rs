@@ -0,0 +1,321 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use futures::future::{join_all, BoxFuture};
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncSmtpTransport,
+    AsyncTransport, Message, Tokio1Executor,
+};
+use log::{debug, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, time::timeout};
+
+use crate::error::DmError;
+
+// 单个通知的最长发送时间
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 通知事件类型
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyEvent {
+    /// 抢票成功
+    Success,
+    /// 抢票最终失败
+    Failure,
+    /// 登录失效, 需要更新cookie
+    CookieExpired,
+    /// 启动或运行出错
+    StartupError,
+    /// 订单付款提醒及最终付款状态
+    Payment,
+}
+
+impl NotifyEvent {
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Success => "抢票成功",
+            Self::Failure => "抢票失败",
+            Self::CookieExpired => "登录失效",
+            Self::StartupError => "运行出错",
+            Self::Payment => "订单付款提醒",
+        }
+    }
+}
+
+// 通知内容, webhook以JSON格式发送
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    pub event: NotifyEvent,
+    pub remark: String,
+    pub title: String,
+    pub message: String,
+    pub order_id: Option<String>,
+    pub timestamp: i64,
+}
+
+impl Notification {
+    pub fn new(event: NotifyEvent, remark: &str, message: impl Into<String>) -> Self {
+        Self {
+            event,
+            remark: remark.to_string(),
+            title: format!("[dm-ticket] 账号:{}, {}", remark, event.title()),
+            message: message.into(),
+            order_id: None,
+            timestamp: Local::now().timestamp_millis(),
+        }
+    }
+
+    pub fn with_order(mut self, order_id: &str) -> Self {
+        self.order_id = Some(order_id.to_string());
+        self
+    }
+
+    // 根据错误类型区分登录失效和其他错误
+    pub fn from_error(remark: &str, e: &anyhow::Error) -> Self {
+        let event = match e.downcast_ref::<DmError>() {
+            Some(e) if e.is_session_invalid() => NotifyEvent::CookieExpired,
+            _ => NotifyEvent::StartupError,
+        };
+        Self::new(event, remark, e.to_string())
+    }
+}
+
+/// 通知的发送方式.
+pub trait Notifier: Send + Sync {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>>;
+}
+
+// 以JSON格式POST到指定URL
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut request = self.client.post(&self.url).json(notification);
+            for (name, value) in self.headers.iter() {
+                request = request.header(name, value);
+            }
+            request.send().await?.error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+// 通过SMTP发送邮件
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl Notifier for EmailNotifier {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mut builder = Message::builder()
+                .from(self.from.clone())
+                .subject(&notification.title);
+            for to in self.to.iter() {
+                builder = builder.to(to.clone());
+            }
+            let message = builder.body(notification.message.clone())?;
+            self.transport.send(message).await?;
+            Ok(())
+        })
+    }
+}
+
+// 执行命令, 通知内容通过环境变量传入
+pub struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let status = Command::new(&self.program)
+                .args(&self.args)
+                .env(
+                    "DM_EVENT",
+                    serde_json::to_string(&notification.event)?.trim_matches('"'),
+                )
+                .env("DM_REMARK", &notification.remark)
+                .env("DM_TITLE", &notification.title)
+                .env("DM_MESSAGE", &notification.message)
+                .env(
+                    "DM_ORDER_ID",
+                    notification.order_id.as_deref().unwrap_or_default(),
+                )
+                .env("DM_NOTIFICATION", serde_json::to_string(notification)?)
+                .kill_on_drop(true)
+                .status()
+                .await?;
+            match status.success() {
+                true => Ok(()),
+                false => Err(anyhow!("命令:{}退出状态:{}", self.program, status)),
+            }
+        })
+    }
+}
+
+/// SMTP连接的加密方式
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// 直接使用TLS连接, 通常为465端口
+    #[default]
+    Tls,
+    /// 通过STARTTLS升级, 通常为587端口
+    Starttls,
+    /// 不加密
+    None,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NotifierKind {
+    /// 以JSON格式POST到指定URL
+    Webhook {
+        url: String,
+        /// 附加的请求头
+        headers: Option<HashMap<String, String>>,
+    },
+    /// 通过SMTP发送邮件
+    Email {
+        /// SMTP服务器地址
+        host: String,
+        port: Option<u16>,
+        /// 加密方式, 默认tls
+        tls: Option<SmtpTls>,
+        username: Option<String>,
+        /// 密码或授权码
+        password: Option<String>,
+        /// 发件人
+        from: String,
+        /// 收件人列表
+        to: Vec<String>,
+    },
+    /// 执行命令, 通知内容通过环境变量DM_EVENT, DM_REMARK, DM_TITLE, DM_MESSAGE, DM_ORDER_ID, DM_NOTIFICATION传入
+    Command {
+        program: String,
+        args: Option<Vec<String>>,
+    },
+}
+
+impl NotifierKind {
+    // 用于日志, 不包含密码等信息
+    fn describe(&self) -> String {
+        match self {
+            Self::Webhook { url, .. } => format!("webhook({})", url),
+            Self::Email { host, .. } => format!("email({})", host),
+            Self::Command { program, .. } => format!("command({})", program),
+        }
+    }
+}
+
+/// 通知配置
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct NotifierConfig {
+    #[serde(flatten)]
+    pub kind: NotifierKind,
+    /// 需要通知的事件, 不填写则通知所有事件
+    pub events: Option<Vec<NotifyEvent>>,
+}
+
+impl NotifierConfig {
+    pub fn build(&self) -> Result<Box<dyn Notifier>> {
+        let notifier: Box<dyn Notifier> = match &self.kind {
+            NotifierKind::Webhook { url, headers } => Box::new(WebhookNotifier {
+                client: reqwest::Client::builder().use_rustls_tls().build()?,
+                url: url.clone(),
+                headers: headers.clone().unwrap_or_default(),
+            }),
+            NotifierKind::Email {
+                host,
+                port,
+                tls,
+                username,
+                password,
+                from,
+                to,
+            } => {
+                let mut builder = match tls.clone().unwrap_or_default() {
+                    SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+                    SmtpTls::Starttls => {
+                        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+                    }
+                    SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+                };
+                if let Some(port) = port {
+                    builder = builder.port(*port);
+                }
+                if let (Some(username), Some(password)) = (username, password) {
+                    builder =
+                        builder.credentials(Credentials::new(username.clone(), password.clone()));
+                }
+                Box::new(EmailNotifier {
+                    transport: builder.timeout(Some(NOTIFY_TIMEOUT)).build(),
+                    from: from.parse()?,
+                    to: to
+                        .iter()
+                        .map(|t| t.parse::<Mailbox>())
+                        .collect::<Result<Vec<_>, _>>()?,
+                })
+            }
+            NotifierKind::Command { program, args } => Box::new(CommandNotifier {
+                program: program.clone(),
+                args: args.clone().unwrap_or_default(),
+            }),
+        };
+        Ok(notifier)
+    }
+
+    fn accepts(&self, event: NotifyEvent) -> bool {
+        self.events
+            .as_ref()
+            .map(|events| events.contains(&event))
+            .unwrap_or(true)
+    }
+}
+
+// 账号配置的所有通知
+#[derive(Default)]
+pub struct Notifiers {
+    notifiers: Vec<(NotifierConfig, Box<dyn Notifier>)>,
+}
+
+impl Notifiers {
+    pub fn new(configs: &[NotifierConfig]) -> Result<Self> {
+        let notifiers = configs
+            .iter()
+            .map(|config| Ok((config.clone(), config.build()?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { notifiers })
+    }
+
+    // 并发发送通知, 发送失败只记录日志
+    pub async fn notify(&self, notification: Notification) {
+        let tasks = self
+            .notifiers
+            .iter()
+            .filter(|(config, _)| config.accepts(notification.event))
+            .map(|(config, notifier)| {
+                let notification = &notification;
+                async move {
+                    match timeout(NOTIFY_TIMEOUT, notifier.notify(notification)).await {
+                        Ok(Ok(())) => debug!("发送通知成功:{}", config.kind.describe()),
+                        Ok(Err(e)) => warn!("发送通知失败:{}, {}", config.kind.describe(), e),
+                        Err(_) => warn!("发送通知超时:{}", config.kind.describe()),
+                    }
+                }
+            });
+        join_all(tasks).await;
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use chrono::Local;
+use log::{error, info, warn};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dm::DmTicket,
+    models::order::{OrderState, OrderSummary},
+    notify::{Notification, Notifiers, NotifyEvent},
+    scheduler::ms_to_hms,
+};
+
+/// 待付款订单的提醒策略, 时间单位为毫秒
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct PayWatch {
+    /// 查询订单状态的间隔
+    pub interval: u64,
+
+    /// 距付款截止不足该时间时, 提醒升级为警告, 响铃并发送通知
+    pub remind_before: u64,
+
+    /// 距付款截止不足该时间时, 再次发送通知, 之后每次查询都响铃
+    pub urgent_before: u64,
+
+    /// 无法获取付款截止时间时, 最长的监控时间
+    pub max_duration: u64,
+}
+
+impl Default for PayWatch {
+    fn default() -> Self {
+        Self {
+            interval: 10_000,
+            remind_before: 300_000,
+            urgent_before: 60_000,
+            max_duration: 1_800_000,
+        }
+    }
+}
+
+// 提醒级别, 按紧急程度递增
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Urgency {
+    Normal,
+    Remind,
+    Urgent,
+}
+
+impl PayWatch {
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval.max(1000))
+    }
+
+    // remaining为距付款截止的剩余时间(毫秒), 未知时为None
+    pub fn urgency(&self, remaining: Option<i64>) -> Urgency {
+        match remaining {
+            Some(r) if r <= self.urgent_before as i64 => Urgency::Urgent,
+            Some(r) if r <= self.remind_before as i64 => Urgency::Remind,
+            _ => Urgency::Normal,
+        }
+    }
+}
+
+// 通知并打印付款监控的最终状态
+pub async fn report_payment(
+    notifiers: &Notifiers,
+    remark: &str,
+    order_id: &str,
+    state: OrderState,
+) {
+    let message = match state {
+        OrderState::Paid => format!("订单:{}已付款.", order_id),
+        OrderState::Closed => format!("订单:{}已关闭, 未在付款时限内完成付款.", order_id),
+        OrderState::Unpaid => {
+            format!(
+                "订单:{}已超过付款截止时间仍未付款, 订单可能已失效.",
+                order_id
+            )
+        }
+        OrderState::Unknown => format!("订单:{}状态未知, 请前往手机APP查看并及时付款.", order_id),
+    };
+    match state {
+        OrderState::Paid => info!("账号:{}, {}", remark, message),
+        OrderState::Unknown => warn!("账号:{}, {}", remark, message),
+        _ => error!("账号:{}, {}", remark, message),
+    }
+    notifiers
+        .notify(Notification::new(NotifyEvent::Payment, remark, message).with_order(order_id))
+        .await;
+}
+
+// 轮询待付款订单状态, 临近付款截止时逐级提醒, 返回最终状态.
+// 订单详情未解析到任何字段时无法判断状态, 立即停止监控
+pub async fn watch_payment(
+    dm_ticket: &DmTicket,
+    notifiers: &Notifiers,
+    summary: OrderSummary,
+    watch: &PayWatch,
+) -> OrderState {
+    let remark = &dm_ticket.account.remark;
+    let order_id = summary.order_id.clone();
+    let started = Local::now().timestamp_millis();
+    let mut summary = summary;
+    let mut last_urgency = None;
+
+    loop {
+        if summary.is_empty() {
+            warn!(
+                "账号:{}, 订单:{}详情中未解析到付款状态, 停止监控付款状态",
+                remark, order_id
+            );
+            return OrderState::Unknown;
+        }
+
+        let state = summary.state();
+        if state != OrderState::Unpaid && state != OrderState::Unknown {
+            return state;
+        }
+
+        let now = Local::now().timestamp_millis();
+        let remaining = summary.pay_deadline.map(|t| t - now);
+        match remaining {
+            Some(r) if r <= 0 => return state,
+            None if now - started >= watch.max_duration as i64 => return state,
+            _ => {}
+        }
+
+        let urgency = watch.urgency(remaining);
+        let left = remaining
+            .map(|r| {
+                let (h, m, s) = ms_to_hms(r);
+                format!("{:02}:{:02}:{:02}", h, m, s as u64)
+            })
+            .unwrap_or_else(|| "未知".to_string());
+        match urgency {
+            Urgency::Normal => info!(
+                "账号:{}, 订单:{}待付款, 剩余付款时间:{}",
+                remark, order_id, left
+            ),
+            Urgency::Remind => warn!(
+                "账号:{}, 订单:{}仍未付款, 剩余付款时间:{}, 请尽快前往手机APP付款!",
+                remark, order_id, left
+            ),
+            Urgency::Urgent => error!(
+                "账号:{}, 订单:{}即将超时关闭, 剩余付款时间:{}, 请立即付款!!!",
+                remark, order_id, left
+            ),
+        }
+
+        // 提醒级别升高时发送通知
+        if urgency > Urgency::Normal && last_urgency != Some(urgency) {
+            let message = match urgency {
+                Urgency::Urgent => format!(
+                    "订单:{}即将超时关闭, 剩余付款时间:{}, 请立即付款!",
+                    order_id, left
+                ),
+                _ => format!(
+                    "订单:{}仍未付款, 剩余付款时间:{}, 请尽快付款!",
+                    order_id, left
+                ),
+            };
+            notifiers
+                .notify(
+                    Notification::new(NotifyEvent::Payment, remark, message).with_order(&order_id),
+                )
+                .await;
+        }
+
+        // 提醒级别升高时响铃, 紧急时每次都响铃
+        if urgency == Urgency::Urgent
+            || (urgency > Urgency::Normal && last_urgency != Some(urgency))
+        {
+            print!("\x07");
+            let _ = io::stdout().flush();
+        }
+        last_urgency = Some(urgency);
+
+        let delay = match remaining {
+            Some(r) if r > 0 => watch.interval().min(Duration::from_millis(r as u64)),
+            _ => watch.interval(),
+        };
+        tokio::time::sleep(delay).await;
+
+        match dm_ticket.get_order_summary(&order_id).await {
+            Ok(latest) => summary = latest,
+            Err(e) => warn!("查询订单:{}状态失败, {}", order_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{capture::ReplayTransport, client::DmClient, config::Account};
+
+    const ACCOUNT: &str = r#"
+cookie: "cna=abc"
+remark: 付款
+ticket:
+  id: "710947802955"
+  num: 1
+  sessions: 1
+  grade: 1
+"#;
+
+    #[test]
+    fn urgency() {
+        let watch = PayWatch::default();
+        let cases = [
+            (None, Urgency::Normal),
+            (Some(600_000), Urgency::Normal),
+            (Some(300_000), Urgency::Remind),
+            (Some(60_001), Urgency::Remind),
+            (Some(60_000), Urgency::Urgent),
+            (Some(0), Urgency::Urgent),
+        ];
+        for (remaining, expected) in cases {
+            assert_eq!(watch.urgency(remaining), expected, "{:?}", remaining);
+        }
+    }
+
+    #[tokio::test]
+    async fn stop_on_empty_summary() {
+        let account: Account = serde_yaml::from_str(ACCOUNT).unwrap();
+        // 没有回放记录, 查询订单详情会失败
+        let transport = Arc::new(ReplayTransport::new(vec![]));
+        let client = DmClient::with_transport(account.cookie.clone(), transport)
+            .await
+            .unwrap();
+        let dm_ticket = DmTicket::with_client(account, client);
+
+        let summary = OrderSummary {
+            order_id: "9000000".to_string(),
+            ..Default::default()
+        };
+        let state = tokio::time::timeout(
+            Duration::from_secs(1),
+            watch_payment(
+                &dm_ticket,
+                &Notifiers::default(),
+                summary,
+                &PayWatch::default(),
+            ),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state, OrderState::Unknown);
+    }
+}
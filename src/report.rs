@@ -0,0 +1,120 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use serde::Serialize;
+
+use crate::dm::BuyTarget;
+
+// 默认的运行报告目录
+pub const DEFAULT_REPORT_DIR: &str = "./reports";
+
+// 一次mtop请求的记录, 时间单位为毫秒
+#[derive(Serialize, Debug, Clone)]
+pub struct RequestRecord {
+    pub api: String,
+    pub started_at: i64,
+    pub latency: f64,
+    pub ret: Vec<String>,
+    // 网络错误等未收到响应的原因
+    pub error: Option<String>,
+}
+
+// 运行步骤的记录, 时间单位为毫秒
+#[derive(Serialize, Debug, Clone)]
+pub struct StepRecord {
+    pub name: String,
+    pub started_at: i64,
+    pub latency: f64,
+    pub error: Option<String>,
+}
+
+impl StepRecord {
+    pub fn new(name: &str, started_at: i64, start: Instant, error: Option<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            started_at,
+            latency: start.elapsed().as_secs_f64() * 1000.0,
+            error,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    // 未运行到抢购结束, 如启动失败
+    #[default]
+    Unfinished,
+    Success,
+    Failure,
+    DryRun,
+    Cancelled,
+    Error,
+}
+
+// 账号单次运行的报告
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RunReport {
+    pub remark: String,
+    pub item_id: String,
+    pub item_name: Option<String>,
+    pub targets: Vec<BuyTarget>,
+    pub sell_start_time: Option<String>,
+    pub sell_start_timestamp: Option<i64>,
+    // 校时得到的服务器时间偏差(毫秒)
+    pub clock_offset: Option<i64>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub steps: Vec<StepRecord>,
+    pub requests: Vec<RequestRecord>,
+    pub outcome: RunOutcome,
+    pub message: Option<String>,
+    pub order_id: Option<String>,
+}
+
+impl RunReport {
+    pub fn new(remark: &str, item_id: &str) -> Self {
+        Self {
+            remark: remark.to_string(),
+            item_id: item_id.to_string(),
+            started_at: Local::now().timestamp_millis(),
+            ..Default::default()
+        }
+    }
+
+    pub fn finish(&mut self, outcome: RunOutcome, message: Option<String>) {
+        self.outcome = outcome;
+        self.message = message;
+    }
+
+    // 写入dir/{remark}-{开始时间}.json, 返回文件路径
+    pub fn write(&self, dir: &str) -> Result<PathBuf> {
+        fs::create_dir_all(dir)?;
+
+        let remark = self
+            .remark
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' | ' ' => '_',
+                c => c,
+            })
+            .collect::<String>();
+        let started_at = Local
+            .timestamp_millis_opt(self.started_at)
+            .single()
+            .unwrap_or_else(Local::now)
+            .format("%Y%m%d-%H%M%S");
+
+        let path = Path::new(dir).join(format!("{}-{}.json", remark, started_at));
+        // 先写临时文件再重命名, 覆盖写入时进程被终止也不会留下不完整的报告
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+}
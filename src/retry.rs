@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::error::DmError;
+
+// 抢购失败的原因分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    // 请求被限流
+    Throttled,
+    // 已售罄
+    SoldOut,
+    // 登录失效
+    SessionInvalid,
+    // 未开售、响应解析失败等业务错误
+    Business,
+    // 网络错误
+    Network,
+    // 配置或数据错误, 重试无意义
+    Fatal,
+}
+
+impl ErrorClass {
+    pub fn of(e: &anyhow::Error) -> Self {
+        if let Some(e) = e.downcast_ref::<DmError>() {
+            return match e {
+                DmError::Throttled(_) => Self::Throttled,
+                DmError::SoldOut(_) => Self::SoldOut,
+                DmError::SessionInvalid(_) => Self::SessionInvalid,
+                DmError::TokenExpired(_) | DmError::Business { .. } => Self::Business,
+            };
+        }
+        // 响应结构异常多为接口临时返回的错误页、滑块页或降级数据, 按业务错误重试
+        if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+            return if e.is_decode() {
+                Self::Business
+            } else {
+                Self::Network
+            };
+        }
+        match e.downcast_ref::<serde_json::Error>() {
+            Some(_) => Self::Business,
+            None => Self::Fatal,
+        }
+    }
+}
+
+/// 失败后的处理方式
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryAction {
+    /// 重试
+    Retry,
+    /// 停止抢购
+    Stop,
+}
+
+/// 抢购重试策略, 时间单位为毫秒
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// 最大尝试次数
+    #[schemars(range(min = 1))]
+    pub max_attempts: u32,
+
+    /// 从第一次尝试开始计算的最长重试时间
+    pub deadline: u64,
+
+    /// 每次重试的间隔
+    pub backoff: u64,
+
+    /// 被限流时的初始间隔, 连续限流时翻倍, 不超过max_backoff
+    pub throttle_backoff: u64,
+    /// 被限流时的最大间隔
+    pub max_backoff: u64,
+
+    /// 被限流时的处理方式
+    pub on_throttled: RetryAction,
+    /// 售罄时的处理方式
+    pub on_sold_out: RetryAction,
+    /// 未开售等业务错误时的处理方式
+    pub on_business_error: RetryAction,
+    /// 网络错误时的处理方式
+    pub on_network_error: RetryAction,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 30,
+            deadline: 60_000,
+            backoff: 100,
+            throttle_backoff: 300,
+            max_backoff: 3000,
+            on_throttled: RetryAction::Retry,
+            on_sold_out: RetryAction::Stop,
+            on_business_error: RetryAction::Retry,
+            on_network_error: RetryAction::Retry,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn action(&self, class: ErrorClass) -> RetryAction {
+        match class {
+            ErrorClass::Throttled => self.on_throttled,
+            ErrorClass::SoldOut => self.on_sold_out,
+            ErrorClass::Business => self.on_business_error,
+            ErrorClass::Network => self.on_network_error,
+            ErrorClass::SessionInvalid | ErrorClass::Fatal => RetryAction::Stop,
+        }
+    }
+
+    pub fn deadline(&self) -> Duration {
+        Duration::from_millis(self.deadline)
+    }
+
+    // 下次重试前的等待时间, throttled为连续限流次数
+    pub fn delay(&self, class: ErrorClass, throttled: u32) -> Duration {
+        let millis = match class {
+            ErrorClass::Throttled => self
+                .throttle_backoff
+                .saturating_mul(1 << throttled.saturating_sub(1).min(16))
+                .min(self.max_backoff),
+            _ => self.backoff,
+        };
+        Duration::from_millis(millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_class() {
+        let cases = [
+            ("RGV587_ERROR::SM::哎哟喂,被挤爆啦", ErrorClass::Throttled),
+            ("FAIL_SYS_TOKEN_EXOIRED::令牌过期", ErrorClass::Business),
+            (
+                "FAIL_SYS_SESSION_EXPIRED::Session过期",
+                ErrorClass::SessionInvalid,
+            ),
+            ("B-00203-200-001::库存不足", ErrorClass::SoldOut),
+            ("B-00203-200-100::网络开小差了", ErrorClass::Business),
+        ];
+
+        for (ret, expected) in cases {
+            let e = anyhow::Error::from(DmError::parse(ret).unwrap());
+            assert_eq!(ErrorClass::of(&e), expected, "ret: {}", ret);
+        }
+        assert_eq!(
+            ErrorClass::of(&anyhow::anyhow!("未找到实名观演人")),
+            ErrorClass::Fatal
+        );
+
+        let e = serde_json::from_value::<Vec<String>>(serde_json::json!({})).unwrap_err();
+        assert_eq!(ErrorClass::of(&e.into()), ErrorClass::Business);
+    }
+
+    // 接口返回HTML页面时, reqwest解析JSON失败
+    #[tokio::test]
+    async fn decode_error() {
+        use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let body = "<html>滑块验证</html>";
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let e = reqwest::get(format!("http://{}/", addr))
+            .await
+            .unwrap()
+            .json::<crate::models::DmRes>()
+            .await
+            .unwrap_err();
+        assert!(e.is_decode(), "{:?}", e);
+        assert_eq!(ErrorClass::of(&e.into()), ErrorClass::Business);
+    }
+
+    #[test]
+    fn delay() {
+        let policy = RetryPolicy {
+            backoff: 100,
+            throttle_backoff: 300,
+            max_backoff: 3000,
+            ..Default::default()
+        };
+
+        // (失败原因, 连续限流次数, 等待时间)
+        let cases = [
+            (ErrorClass::Business, 0, 100),
+            (ErrorClass::Network, 0, 100),
+            (ErrorClass::SoldOut, 0, 100),
+            (ErrorClass::Throttled, 1, 300),
+            (ErrorClass::Throttled, 2, 600),
+            (ErrorClass::Throttled, 3, 1200),
+            (ErrorClass::Throttled, 4, 2400),
+            (ErrorClass::Throttled, 5, 3000),
+            (ErrorClass::Throttled, 100, 3000),
+        ];
+
+        for (class, throttled, millis) in cases {
+            assert_eq!(
+                policy.delay(class, throttled),
+                Duration::from_millis(millis),
+                "{:?}, throttled: {}",
+                class,
+                throttled
+            );
+        }
+    }
+
+    #[test]
+    fn action() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.action(ErrorClass::Throttled), RetryAction::Retry);
+        assert_eq!(policy.action(ErrorClass::SoldOut), RetryAction::Stop);
+        assert_eq!(policy.action(ErrorClass::SessionInvalid), RetryAction::Stop);
+        assert_eq!(policy.action(ErrorClass::Fatal), RetryAction::Stop);
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::clock::ServerClock;
+
+// 距离目标时间大于该值时粗略休眠, 之后使用sleep_until精确唤醒
+const COARSE_MARGIN_MILLIS: i64 = 2000;
+
+// 粗略休眠的最长时间, 每次醒来按最新的时钟偏差重新计算, 开抢前重新校时后即可生效
+const MAX_COARSE_SLEEP_MILLIS: i64 = 60_000;
+
+// 毫秒转换为时分秒
+pub fn ms_to_hms(ms: i64) -> (u64, u64, f64) {
+    let sec = ms as f64 / 1000.0;
+    let hour = (sec / 3600.0) as u64;
+    let rem = sec % 3600.0;
+    let min = (rem / 60.0) as u64;
+    let sec = rem % 60.0;
+    (hour, min, sec)
+}
+
+/// 等待直到服务器时间到达target_millis.
+///
+/// 先分段粗略休眠到目标前COARSE_MARGIN_MILLIS, 再按校正后的时间
+/// 计算出单调时钟上的Instant, 通过sleep_until一次唤醒.
+pub async fn wait_until(clock: &ServerClock, target_millis: i64) {
+    loop {
+        let left = target_millis - clock.now_millis();
+        if left <= 0 {
+            return;
+        }
+        if left > COARSE_MARGIN_MILLIS {
+            let coarse = (left - COARSE_MARGIN_MILLIS).min(MAX_COARSE_SLEEP_MILLIS);
+            sleep(Duration::from_millis(coarse as u64)).await;
+            continue;
+        }
+        sleep_until(Instant::now() + Duration::from_millis(left as u64)).await;
+        return;
+    }
+}
+
+// 打印开抢倒计时, 不会主动结束
+pub async fn countdown(clock: &ServerClock, start_millis: i64, interval: u64) {
+    let mut ticker = tokio::time::interval(Duration::from_millis(interval.max(1)));
+    loop {
+        ticker.tick().await;
+        let time_left_millis = (start_millis - clock.now_millis()).max(0);
+        let (hours, minutes, seconds) = ms_to_hms(time_left_millis);
+        print!(
+            "\r\t开抢倒计时:{}小时:{}分钟:{:.3}秒\t",
+            hours, minutes, seconds
+        );
+        let _ = io::stdout().flush();
+    }
+}
@@ -0,0 +1,267 @@
+use std::env;
+
+use anyhow::{anyhow, Result};
+use chrono::DateTime;
+use futures::future::BoxFuture;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, COOKIE, DATE},
+    Client,
+};
+use serde_json::Value;
+
+use crate::{
+    client::TokenClient,
+    models::{ticket::TicketInfoParams, DmRes, DmToken},
+};
+
+pub const DEFAULT_BASE_URL: &str = "https://mtop.damai.cn/";
+
+// 获取_m_h5_tk使用的接口
+const TOKEN_PATH: &str = "h5/mtop.damai.wireless.search.broadcast.list/1.0/?";
+
+// 已签名的mtop请求
+#[derive(Debug, Clone)]
+pub struct MtopRequest {
+    // 相对base_url的路径, 如: h5/mtop.trade.order.build.h5/4.0/
+    pub path: String,
+    pub params: Value,
+    pub form: Value,
+    // 完整cookie, 包含_m_h5_tk/_m_h5_tk_enc
+    pub cookie: String,
+}
+
+/// mtop请求的发送方式, 默认使用reqwest请求大麦网, 测试时可替换为本地实现.
+pub trait Transport: Send + Sync {
+    /// 发送已签名的请求.
+    fn send(&self, request: MtopRequest) -> BoxFuture<'_, Result<DmRes>>;
+
+    /// 使用cookie获取_m_h5_tk/_m_h5_tk_enc.
+    fn fetch_token<'a>(&'a self, cookie: &'a str) -> BoxFuture<'a, Result<DmToken>>;
+
+    /// 服务器响应头Date的时间戳(毫秒), 用于校时.
+    fn server_date(&self) -> BoxFuture<'_, Result<i64>>;
+
+    /// 获取bx-umidtoken, 创建客户端时调用一次. 本地实现可返回固定值.
+    fn bx_token(&self) -> BoxFuture<'_, Result<String>>;
+
+    /// 获取bx-ua, 每次请求前调用. 本地实现可返回固定值.
+    fn bx_ua(&self) -> BoxFuture<'_, Result<String>>;
+}
+
+pub struct ReqwestTransport {
+    pub client: Client,
+    pub base_url: String,
+    // 从token-server获取bx-umidtoken/bx-ua
+    pub token_client: TokenClient,
+}
+
+impl ReqwestTransport {
+    pub fn new(base_url: &str) -> Result<Self> {
+        let base_url = match base_url.ends_with('/') {
+            true => base_url.to_string(),
+            false => format!("{}/", base_url),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.append("origin", HeaderValue::from_str(&base_url)?);
+        headers.append("referer", HeaderValue::from_str(&base_url)?);
+
+        let mut builder = reqwest::Client::builder()
+            .default_headers(headers)
+            .cookie_store(true)
+            .user_agent("Mozilla/5.0 (iPhone; CPU iPhone OS 13_2_3 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/13.0.3")
+            .use_rustls_tls();
+
+        // 本地http服务不一定支持h2c, 仅https时启用
+        if base_url.starts_with("https://") {
+            builder = builder.http2_prior_knowledge();
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            base_url,
+            token_client: TokenClient::new()?,
+        })
+    }
+
+    // 读取环境变量MTOP_BASE_URL, 默认请求大麦网.
+    pub fn from_env() -> Result<Self> {
+        let base_url = env::var("MTOP_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+        Self::new(&base_url)
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path.trim_start_matches('/'))
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: MtopRequest) -> BoxFuture<'_, Result<DmRes>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(self.url(&request.path))
+                .header(COOKIE, HeaderValue::from_str(&request.cookie)?)
+                .query(&request.params)
+                .form(&request.form)
+                .send()
+                .await?;
+
+            let data = response.json::<DmRes>().await?;
+
+            Ok(data)
+        })
+    }
+
+    fn fetch_token<'a>(&'a self, cookie: &'a str) -> BoxFuture<'a, Result<DmToken>> {
+        Box::pin(async move {
+            let mut token = DmToken {
+                enc_token: "".to_string(),
+                token_with_time: "".to_string(),
+                token: "".to_string(),
+            };
+
+            let params = TicketInfoParams::build()?;
+            let response = self
+                .client
+                .get(self.url(TOKEN_PATH))
+                .header(COOKIE, HeaderValue::from_str(cookie)?)
+                .form(&params)
+                .send()
+                .await?;
+
+            for cookie in response.cookies() {
+                if cookie.name() == "_m_h5_tk" {
+                    token.token_with_time = cookie.value().to_string();
+                    token.token =
+                        token.token_with_time.split('_').collect::<Vec<_>>()[0].to_string();
+                }
+                if cookie.name() == "_m_h5_tk_enc" {
+                    token.enc_token = cookie.value().to_string();
+                }
+            }
+            Ok(token)
+        })
+    }
+
+    fn server_date(&self) -> BoxFuture<'_, Result<i64>> {
+        Box::pin(async move {
+            let response = self.client.head(&self.base_url).send().await?;
+            let date = response
+                .headers()
+                .get(DATE)
+                .ok_or_else(|| anyhow!("响应头缺少Date"))?
+                .to_str()?;
+            Ok(DateTime::parse_from_rfc2822(date)?.timestamp_millis())
+        })
+    }
+
+    fn bx_token(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(self.token_client.get_bx_token())
+    }
+
+    fn bx_ua(&self) -> BoxFuture<'_, Result<String>> {
+        Box::pin(self.token_client.get_bx_ua())
+    }
+}
+
+/// 测试用的Transport, 按api和请求data返回脚本化的响应, 并记录发送的请求.
+#[cfg(test)]
+pub mod fake {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use super::*;
+    use crate::error::SUCCESS_FLAG;
+
+    type Handler = Box<dyn Fn(&str, &Value) -> Result<DmRes> + Send + Sync>;
+
+    pub struct FakeTransport {
+        handler: Handler,
+        pub requests: Mutex<Vec<MtopRequest>>,
+        // fetch_token的调用次数, 每次返回不同的令牌
+        pub token_fetches: AtomicUsize,
+    }
+
+    impl FakeTransport {
+        // handler的参数为api和请求的data
+        pub fn new(
+            handler: impl Fn(&str, &Value) -> Result<DmRes> + Send + Sync + 'static,
+        ) -> Self {
+            Self {
+                handler: Box::new(handler),
+                requests: Mutex::new(Vec::new()),
+                token_fetches: AtomicUsize::new(0),
+            }
+        }
+
+        // 已发送请求的api
+        pub fn apis(&self) -> Vec<String> {
+            self.requests
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|r| r.params["api"].as_str().unwrap_or_default().to_string())
+                .collect()
+        }
+    }
+
+    pub fn success(data: Value) -> DmRes {
+        DmRes {
+            api: None,
+            data,
+            ret: vec![SUCCESS_FLAG.to_string()],
+            v: None,
+        }
+    }
+
+    pub fn failure(ret: &str) -> DmRes {
+        DmRes {
+            api: None,
+            data: Value::Null,
+            ret: vec![ret.to_string()],
+            v: None,
+        }
+    }
+
+    impl Transport for FakeTransport {
+        fn send(&self, request: MtopRequest) -> BoxFuture<'_, Result<DmRes>> {
+            let api = request.params["api"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            let data = request.form["data"]
+                .as_str()
+                .and_then(|d| serde_json::from_str::<Value>(d).ok())
+                .unwrap_or_default();
+            self.requests.lock().unwrap().push(request);
+            let res = (self.handler)(&api, &data);
+            Box::pin(async move { res })
+        }
+
+        fn fetch_token<'a>(&'a self, _cookie: &'a str) -> BoxFuture<'a, Result<DmToken>> {
+            let n = self.token_fetches.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                Ok(DmToken {
+                    token_with_time: format!("token{}_1792324489783", n),
+                    token: format!("token{}", n),
+                    enc_token: format!("enc{}", n),
+                })
+            })
+        }
+
+        fn server_date(&self) -> BoxFuture<'_, Result<i64>> {
+            Box::pin(async move { Ok(0) })
+        }
+
+        fn bx_token(&self) -> BoxFuture<'_, Result<String>> {
+            Box::pin(async move { Ok("bx_token".to_string()) })
+        }
+
+        fn bx_ua(&self) -> BoxFuture<'_, Result<String>> {
+            Box::pin(async move { Ok("bx_ua".to_string()) })
+        }
+    }
+}